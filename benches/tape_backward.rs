@@ -0,0 +1,62 @@
+//! Backward over a ~100k-node graph, arena `Tape` vs the `Rc` graph.
+//! Run with `cargo bench --bench tape_backward`.
+
+use std::time::{Duration, Instant};
+
+use autodiff_rs::engine::Value;
+use autodiff_rs::tape::{Tape, Var};
+
+const N: usize = 25_000;
+const RUNS: usize = 5;
+
+fn tape_backward(inputs: &[f64]) -> Duration {
+    // n leaves, n muls, n tanhs and n - 1 adds
+    let mut tape = Tape::with_capacity(4 * inputs.len());
+    let w = tape.leaf(0.5);
+    let terms: Vec<Var> = inputs
+        .iter()
+        .map(|&x| {
+            let x = tape.leaf(x);
+            let wx = tape.mul(w, x);
+            tape.tanh(wx)
+        })
+        .collect();
+    let root = tape.sum(&terms);
+    let start = Instant::now();
+    tape.backward(root);
+    start.elapsed()
+}
+
+fn rc_backward(inputs: &[f64]) -> Duration {
+    let w = Value::new(0.5);
+    let mut level: Vec<Value> = inputs
+        .iter()
+        .map(|&x| (&w * &Value::new(x)).tanh())
+        .collect();
+    // pairwise, like Tape::sum, to keep the graph shallow
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|c| {
+                if c.len() == 2 {
+                    &c[0] + &c[1]
+                } else {
+                    c[0].clone()
+                }
+            })
+            .collect();
+    }
+    let start = Instant::now();
+    level[0].backward();
+    start.elapsed()
+}
+
+fn main() {
+    let inputs: Vec<f64> = (0..N).map(|i| (i as f64 * 0.37).sin()).collect();
+    let best = |f: fn(&[f64]) -> Duration| (0..RUNS).map(|_| f(&inputs)).min().unwrap();
+    let (tape, rc) = (best(tape_backward), best(rc_backward));
+    println!("backward over ~{} nodes (best of {}):", 4 * N, RUNS);
+    println!("  tape     {:?}", tape);
+    println!("  Rc graph {:?}", rc);
+    println!("  ratio    {:.1}x", rc.as_secs_f64() / tape.as_secs_f64());
+}
@@ -0,0 +1,16 @@
+//! Conversions between `ndarray` vectors and graph nodes, behind the
+//! `ndarray` feature.
+
+use ndarray::Array1;
+
+use crate::engine::Value;
+
+/// Fresh leaf nodes holding `x`'s elements.
+pub fn to_values(x: &Array1<f64>) -> Vec<Value> {
+    x.iter().map(|&v| Value::new(v)).collect()
+}
+
+/// The nodes' current data, detached from the graph.
+pub fn to_array(values: &[Value]) -> Array1<f64> {
+    values.iter().map(|v| v.value()).collect()
+}
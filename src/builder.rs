@@ -0,0 +1,174 @@
+use std::fmt;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::init::Init;
+use crate::nn::{Activation, Dropout, Layer, Module, PRelu, Sequential};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BuildError {
+    MissingInput,
+    DuplicateInput,
+    ZeroWidth,
+    InvalidDropout(f64),
+    NoLayers,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::MissingInput => write!(f, "input() must be called before adding layers"),
+            BuildError::DuplicateInput => write!(f, "input() was called more than once"),
+            BuildError::ZeroWidth => write!(f, "widths must be greater than zero"),
+            BuildError::InvalidDropout(p) => write!(f, "dropout rate {} is not in [0, 1)", p),
+            BuildError::NoLayers => write!(f, "network has no layers"),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+enum Spec {
+    Dense {
+        nout: u64,
+        act: Activation,
+        init: Option<Init>,
+    },
+    Dropout(f64),
+    PRelu(f64),
+}
+
+/// Fluent constructor for feed-forward networks:
+///
+/// ```
+/// use autodiff_rs::nn::{Activation, MLP};
+///
+/// let model = MLP::builder()
+///     .input(2)
+///     .dense(4, Activation::Relu)
+///     .dropout(0.1)
+///     .dense(1, Activation::Linear)
+///     .seed(42)
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// Errors in the chain are recorded as they happen and reported by `build`.
+#[derive(Default)]
+pub struct MLPBuilder {
+    input: Option<u64>,
+    specs: Vec<Spec>,
+    init: Option<Init>,
+    bias_init: Option<Init>,
+    seed: Option<u64>,
+    error: Option<BuildError>,
+}
+
+impl MLPBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn fail(&mut self, err: BuildError) {
+        self.error.get_or_insert(err);
+    }
+
+    pub fn input(mut self, nin: u64) -> Self {
+        if self.input.is_some() {
+            self.fail(BuildError::DuplicateInput);
+        } else if nin == 0 {
+            self.fail(BuildError::ZeroWidth);
+        }
+        self.input = Some(nin);
+        self
+    }
+
+    pub fn dense(self, nout: u64, act: Activation) -> Self {
+        self.push_dense(nout, act, None)
+    }
+
+    /// A dense layer with its own init scheme, overriding [`init`](Self::init).
+    pub fn dense_with_init(self, nout: u64, act: Activation, init: Init) -> Self {
+        self.push_dense(nout, act, Some(init))
+    }
+
+    fn push_dense(mut self, nout: u64, act: Activation, init: Option<Init>) -> Self {
+        if self.input.is_none() {
+            self.fail(BuildError::MissingInput);
+        } else if nout == 0 {
+            self.fail(BuildError::ZeroWidth);
+        }
+        self.specs.push(Spec::Dense { nout, act, init });
+        self
+    }
+
+    pub fn dropout(mut self, p: f64) -> Self {
+        if self.input.is_none() {
+            self.fail(BuildError::MissingInput);
+        } else if !(0.0..1.0).contains(&p) {
+            self.fail(BuildError::InvalidDropout(p));
+        }
+        self.specs.push(Spec::Dropout(p));
+        self
+    }
+
+    pub fn prelu(mut self, slope: f64) -> Self {
+        if self.input.is_none() {
+            self.fail(BuildError::MissingInput);
+        }
+        self.specs.push(Spec::PRelu(slope));
+        self
+    }
+
+    /// Init scheme for every dense layer. Without it each layer uses
+    /// [`Init::default_for`] its activation.
+    pub fn init(mut self, init: Init) -> Self {
+        self.init = Some(init);
+        self
+    }
+
+    /// Init scheme for every dense layer's biases; zero by default.
+    pub fn bias_init(mut self, init: Init) -> Self {
+        self.bias_init = Some(init);
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn build(self) -> Result<Sequential, BuildError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+        let mut nin = self.input.ok_or(BuildError::MissingInput)?;
+        if !self.specs.iter().any(|s| matches!(s, Spec::Dense { .. })) {
+            return Err(BuildError::NoLayers);
+        }
+
+        let mut rng = match self.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_rng(&mut rand::rng()),
+        };
+        let mut modules: Vec<Box<dyn Module>> = vec![];
+        for spec in self.specs {
+            match spec {
+                Spec::Dense { nout, act, init } => {
+                    let init = init.or(self.init).unwrap_or(Init::default_for(act));
+                    let bias_init = self.bias_init.unwrap_or(Init::Constant(0.0));
+                    modules.push(Box::new(Layer::with_inits_rng(
+                        nin, nout, act, init, bias_init, &mut rng,
+                    )));
+                    nin = nout;
+                }
+                Spec::Dropout(p) => {
+                    modules.push(Box::new(Dropout::new_seeded(p, rng.random())));
+                }
+                Spec::PRelu(slope) => modules.push(Box::new(PRelu::new(slope))),
+            }
+        }
+        Ok(Sequential::new(modules))
+    }
+}
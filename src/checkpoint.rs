@@ -0,0 +1,139 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+use crate::nn::Module;
+use crate::optim::{Optimizer, OptimizerState};
+
+const HEADER: &str = "autodiff-rs checkpoint 1";
+
+/// Writes the model's parameter values and the optimizer's state to `path`
+/// as plain text, one record per line:
+///
+/// ```text
+/// autodiff-rs checkpoint 1
+/// params <n> <values...>
+/// steps <t>
+/// lr <lr>
+/// pending <n>
+/// buffer <name> <n> <values...>
+/// ```
+///
+/// Values are written with Rust's shortest round-tripping formatting, so a
+/// reload is bit-exact.
+pub fn save_checkpoint(path: &str, model: &dyn Module, opt: &dyn Optimizer) -> io::Result<()> {
+    let mut out = format!("{}\n", HEADER);
+    let params: Vec<f64> = model.parameters().iter().map(|p| p.value()).collect();
+    let _ = writeln!(out, "params {}", record(&params));
+    let state = opt.state();
+    let _ = writeln!(out, "steps {}", state.steps);
+    let _ = writeln!(out, "lr {}", state.lr);
+    let _ = writeln!(out, "pending {}", state.pending);
+    for (name, values) in &state.buffers {
+        let _ = writeln!(out, "buffer {} {}", name, record(values));
+    }
+    fs::write(path, out)
+}
+
+/// Restores a checkpoint written by [`save_checkpoint`] into a model and
+/// optimizer of the same shape. Nothing is modified if the file doesn't
+/// match them; the error kind is then `InvalidData`.
+pub fn load_checkpoint(path: &str, model: &dyn Module, opt: &mut dyn Optimizer) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return Err(invalid("missing checkpoint header".to_string()));
+    }
+
+    let params = model.parameters();
+    let values = parse_record(lines.next(), "params")?;
+    if values.len() != params.len() {
+        return Err(invalid(format!(
+            "checkpoint has {} parameters but the model has {}",
+            values.len(),
+            params.len()
+        )));
+    }
+
+    let steps = parse_scalar(lines.next(), "steps")?;
+    let lr = parse_scalar(lines.next(), "lr")?;
+    let pending = parse_scalar(lines.next(), "pending")?;
+
+    let expected = opt.state();
+    let mut buffers = Vec::with_capacity(expected.buffers.len());
+    for (name, current) in &expected.buffers {
+        let values = parse_record(lines.next(), &format!("buffer {}", name))?;
+        if values.len() != current.len() {
+            return Err(invalid(format!(
+                "buffer {} has {} values but the optimizer has {}",
+                name,
+                values.len(),
+                current.len()
+            )));
+        }
+        buffers.push((name.clone(), values));
+    }
+
+    opt.load_state(&OptimizerState {
+        lr,
+        steps,
+        pending,
+        buffers,
+    })
+    .map_err(|e| invalid(e.to_string()))?;
+    for (p, v) in params.iter().zip(values) {
+        p.0.borrow_mut().data = v;
+    }
+    Ok(())
+}
+
+fn record(values: &[f64]) -> String {
+    let mut out = values.len().to_string();
+    for v in values {
+        let _ = write!(out, " {}", v);
+    }
+    out
+}
+
+fn parse_scalar<T: std::str::FromStr>(line: Option<&str>, tag: &str) -> io::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    let value = line
+        .and_then(|l| l.strip_prefix(tag))
+        .and_then(|l| l.strip_prefix(' '))
+        .ok_or_else(|| invalid(format!("missing {} record", tag)))?;
+    value
+        .parse()
+        .map_err(|e| invalid(format!("bad {} record: {}", tag, e)))
+}
+
+fn parse_record(line: Option<&str>, tag: &str) -> io::Result<Vec<f64>> {
+    let missing = || invalid(format!("missing {} record", tag));
+    let rest = line
+        .and_then(|l| l.strip_prefix(tag))
+        .and_then(|l| l.strip_prefix(' '))
+        .ok_or_else(missing)?;
+    let mut fields = rest.split(' ');
+    let n: usize = fields
+        .next()
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(missing)?;
+    let values = fields
+        .map(|f| f.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| invalid(format!("bad value in {} record: {}", tag, e)))?;
+    if values.len() != n {
+        return Err(invalid(format!(
+            "{} record declares {} values but has {}",
+            tag,
+            n,
+            values.len()
+        )));
+    }
+    Ok(values)
+}
+
+fn invalid(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
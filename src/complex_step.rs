@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::engine::{Data, Ops, Value};
+
+/// Imaginary step. Nothing is subtracted, so it can be far below `f64::EPSILON`.
+const H: f64 = 1e-20;
+
+#[derive(Debug, Clone, Copy)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn real(re: f64) -> Self {
+        Self { re, im: 0.0 }
+    }
+
+    fn exp(self) -> Self {
+        let r = self.re.exp();
+        Self {
+            re: r * self.im.cos(),
+            im: r * self.im.sin(),
+        }
+    }
+
+    fn ln(self) -> Self {
+        Self {
+            re: self.re.hypot(self.im).ln(),
+            im: self.im.atan2(self.re),
+        }
+    }
+
+    fn tanh(self) -> Self {
+        // (tanh a + i tan b) / (1 + i tanh a tan b) stays finite for large |a|
+        let (ta, tb) = (self.re.tanh(), self.im.tan());
+        Self { re: ta, im: tb }
+            / Self {
+                re: 1.0,
+                im: ta * tb,
+            }
+    }
+
+    fn powf(self, p: f64) -> Self {
+        if self.re >= 0.0 {
+            (self.ln() * Self::real(p)).exp()
+        } else {
+            // (-1)^p * (-z)^p, so the branch cut of ln never comes into play;
+            // a real negative base only has a real power for integer p anyway
+            (-self).powf(p) * Self::real((-1f64).powf(p))
+        }
+    }
+
+    fn sigmoid(self) -> Self {
+        // Only ever exponentiate a non-positive real part, as `stable_sigmoid` does
+        if self.re >= 0.0 {
+            Self::real(1.0) / (Self::real(1.0) + (-self).exp())
+        } else {
+            let e = self.exp();
+            e / (Self::real(1.0) + e)
+        }
+    }
+
+    fn softplus(self) -> Self {
+        // Same branches as `Value::softplus`: x + ln(1 + e^-x) for x > 0
+        if self.re > 0.0 {
+            self + (Self::real(1.0) + (-self).exp()).ln()
+        } else {
+            (Self::real(1.0) + self.exp()).ln()
+        }
+    }
+}
+
+impl Add for Complex {
+    type Output = Self;
+    fn add(self, o: Self) -> Self {
+        Self {
+            re: self.re + o.re,
+            im: self.im + o.im,
+        }
+    }
+}
+
+impl Sub for Complex {
+    type Output = Self;
+    fn sub(self, o: Self) -> Self {
+        self + -o
+    }
+}
+
+impl Mul for Complex {
+    type Output = Self;
+    fn mul(self, o: Self) -> Self {
+        Self {
+            re: self.re * o.re - self.im * o.im,
+            im: self.re * o.im + self.im * o.re,
+        }
+    }
+}
+
+impl Div for Complex {
+    type Output = Self;
+    fn div(self, o: Self) -> Self {
+        let d = o.re * o.re + o.im * o.im;
+        Self {
+            re: (self.re * o.re + self.im * o.im) / d,
+            im: (self.im * o.re - self.re * o.im) / d,
+        }
+    }
+}
+
+impl Neg for Complex {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Self {
+            re: -self.re,
+            im: -self.im,
+        }
+    }
+}
+
+/// Gradient of `output` with respect to each of `inputs` by the complex-step
+/// method: the graph is re-evaluated with `x + ih` in place of one input and
+/// `Im(f) / h` is taken as the derivative. With no subtraction there is no
+/// cancellation, so the result agrees with the analytic gradient to machine
+/// precision — a sharper check than finite differences. Non-smooth ops
+/// (relu, abs, max, elu, hardtanh, hardsigmoid) follow the branch picked by the real part.
+///
+/// Only reads the graph; grads are left untouched.
+pub fn complex_step_grads(output: &Value, inputs: &[Value]) -> Vec<f64> {
+    let topo = output.topo_order();
+    inputs
+        .iter()
+        .map(|input| {
+            let mut vals: HashMap<*const Data, Complex> = HashMap::new();
+            for node in &topo {
+                let data = node.0.borrow();
+                let z = match &data.op {
+                    None => Complex {
+                        re: data.data,
+                        im: if node.same_node(input) { H } else { 0.0 },
+                    },
+                    Some(op) => {
+                        let p: Vec<Complex> = data
+                            .parents
+                            .iter()
+                            .map(|v| vals[&(v.0.as_ptr() as *const Data)])
+                            .collect();
+                        eval(op, &p)
+                    }
+                };
+                vals.insert(node.0.as_ptr() as *const Data, z);
+            }
+            vals[&(output.0.as_ptr() as *const Data)].im / H
+        })
+        .collect()
+}
+
+fn eval(op: &Ops, p: &[Complex]) -> Complex {
+    match op {
+        Ops::Add => p[0] + p[1],
+        Ops::Sub => p[0] - p[1],
+        Ops::Mul => p[0] * p[1],
+        Ops::Tanh => p[0].tanh(),
+        Ops::Exp => p[0].exp(),
+        Ops::Log => p[0].ln(),
+        Ops::Pow(e) => p[0].powf(*e),
+        Ops::Relu => {
+            if p[0].re < 0.0 {
+                Complex::real(0.0)
+            } else {
+                p[0]
+            }
+        }
+        Ops::Elu(alpha) => {
+            if p[0].re > 0.0 {
+                p[0]
+            } else {
+                Complex::real(*alpha) * (p[0].exp() - Complex::real(1.0))
+            }
+        }
+        Ops::Softplus => p[0].softplus(),
+        Ops::Sigmoid => p[0].sigmoid(),
+        Ops::Max => {
+            if p[0].re >= p[1].re {
+                p[0]
+            } else {
+                p[1]
+            }
+        }
+        Ops::Abs => {
+            if p[0].re < 0.0 {
+                -p[0]
+            } else {
+                p[0]
+            }
+        }
+        Ops::Hardtanh(min, max) => {
+            if p[0].re < *min {
+                Complex::real(*min)
+            } else if p[0].re > *max {
+                Complex::real(*max)
+            } else {
+                p[0]
+            }
+        }
+        Ops::Hardsigmoid => {
+            let y = Complex::real(0.2) * p[0] + Complex::real(0.5);
+            if y.re < 0.0 {
+                Complex::real(0.0)
+            } else if y.re > 1.0 {
+                Complex::real(1.0)
+            } else {
+                y
+            }
+        }
+        Ops::NanToNum(r) => {
+            if p[0].re.is_finite() {
+                p[0]
+            } else {
+                Complex::real(*r)
+            }
+        }
+        Ops::Sum => p.iter().fold(Complex::real(0.0), |acc, &z| acc + z),
+        Ops::Prod => p.iter().fold(Complex::real(1.0), |acc, &z| acc * z),
+        Ops::Linear => {
+            let n = (p.len() - 1) / 2;
+            (0..n).fold(p[2 * n], |acc, i| acc + p[n + i] * p[i])
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+use crate::engine::Value;
+
+/// One minibatch. `indices` are the rows of the original dataset it holds.
+pub struct Batch {
+    pub inputs: Vec<Vec<Value>>,
+    pub targets: Vec<f64>,
+    pub indices: Vec<usize>,
+}
+
+/// Shuffled minibatches over an in-memory dataset. Every call to `batches`
+/// reshuffles and builds fresh input leaves, so no graph outlives its step.
+pub struct DataLoader {
+    inputs: Vec<Vec<f64>>,
+    targets: Vec<f64>,
+    batch_size: usize,
+    rng: StdRng,
+}
+
+impl DataLoader {
+    pub fn new(inputs: Vec<Vec<f64>>, targets: Vec<f64>, batch_size: usize) -> Self {
+        Self::with_seed(inputs, targets, batch_size, rand::rng().random())
+    }
+
+    pub fn with_seed(
+        inputs: Vec<Vec<f64>>,
+        targets: Vec<f64>,
+        batch_size: usize,
+        seed: u64,
+    ) -> Self {
+        assert_eq!(
+            inputs.len(),
+            targets.len(),
+            "DataLoader: got {} inputs but {} targets",
+            inputs.len(),
+            targets.len()
+        );
+        assert!(batch_size > 0, "DataLoader: batch_size must be positive");
+        Self {
+            inputs,
+            targets,
+            batch_size,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+
+    /// One epoch: every sample exactly once, in a new random order. The last
+    /// batch is smaller when the batch size doesn't divide the dataset.
+    pub fn batches(&mut self) -> impl Iterator<Item = Batch> + '_ {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.shuffle(&mut self.rng);
+        let this = &*self;
+        (0..order.len()).step_by(this.batch_size).map(move |start| {
+            let end = (start + this.batch_size).min(order.len());
+            this.batch(order[start..end].to_vec())
+        })
+    }
+
+    fn batch(&self, indices: Vec<usize>) -> Batch {
+        Batch {
+            inputs: indices
+                .iter()
+                .map(|&i| self.inputs[i].iter().map(|&x| Value::new(x)).collect())
+                .collect(),
+            targets: indices.iter().map(|&i| self.targets[i]).collect(),
+            indices,
+        }
+    }
+}
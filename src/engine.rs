@@ -6,7 +6,11 @@ use std::rc::Rc;
 
 #[cfg(target_os = "windows")]
 use winit::platform::windows::EventLoopBuilderExtWindows;
+#[cfg(target_os = "linux")]
+use winit::platform::x11::EventLoopBuilderExtX11;
 
+use crate::optim::{Accumulation, GroupLrs, ParamGroup, SgdBuilder, group_params};
+pub use crate::optim::{clip_grad_norm, clip_grad_value};
 use crate::visualizer::GraphVisualizer;
 
 #[derive(Debug)]
@@ -19,14 +23,53 @@ pub enum Ops {
     Log,
     Pow(f64),
     Relu,
+    Elu(f64),
+    Softplus,
+    Sigmoid,
+    Max,
+    Abs,
+    Prod,
+    Linear,
+    Hardtanh(f64, f64),
+    Hardsigmoid,
+    NanToNum(f64),
+    Sum,
 }
 
+/// Propagates a node's gradient to its parents. Called with the node's
+/// `parents` and its own `grad`; it captures only plain numbers, never other
+/// nodes, so a graph holds no reference cycles and is freed once its root
+/// (and any other handles into it) are dropped.
+pub type BackwardFn = Box<dyn Fn(&[Value], f64)>;
+
+/// A node in the computational graph.
+///
+/// `data` is treated as immutable once the node has consumers: every backward
+/// closure captures the forward values it needs at construction time, so later
+/// writes to a parent's `data` never leak into the gradients of existing nodes.
+/// After changing leaves under an existing graph (e.g. loading weights), either
+/// run the forward pass again or call [`Value::relink`] on the root.
 pub struct Data {
     pub data: f64,
     pub grad: f64,
     pub parents: Vec<Value>,
     pub op: Option<Ops>,
-    pub _backward: Option<Box<dyn Fn()>>,
+    pub requires_grad: bool,
+    pub _backward: Option<BackwardFn>,
+}
+
+impl Drop for Data {
+    // Dropping a node drops its parents, which drop theirs, and so on; for a
+    // long chain that recursion overflows the stack. Unlink the ancestors we
+    // hold the last handle to onto an explicit stack instead.
+    fn drop(&mut self) {
+        let mut stack = std::mem::take(&mut self.parents);
+        while let Some(v) = stack.pop() {
+            if let Ok(cell) = Rc::try_unwrap(v.0) {
+                stack.append(&mut cell.into_inner().parents);
+            }
+        }
+    }
 }
 
 impl Debug for Data {
@@ -35,10 +78,93 @@ impl Debug for Data {
             .field("data", &self.data)
             .field("grad", &self.grad)
             .field("op", &self.op)
+            .field("requires_grad", &self.requires_grad)
             .finish()
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeInfo {
+    pub id: usize,
+    pub data: f64,
+    pub grad: f64,
+    /// `None` for leaves.
+    pub op: Option<String>,
+}
+
+/// Returned by [`Value::backward_checked`]. `node` is the id the offending
+/// node gets in [`Value::to_adjacency`]; `op` is `None` for leaves.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GradError {
+    NonFiniteData {
+        node: usize,
+        op: Option<String>,
+        data: f64,
+    },
+    NonFiniteGrad {
+        node: usize,
+        op: Option<String>,
+        grad: f64,
+    },
+}
+
+impl std::fmt::Display for GradError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let label = |op: &Option<String>| op.clone().unwrap_or_else(|| "leaf".to_string());
+        match self {
+            GradError::NonFiniteData { node, op, data } => {
+                write!(
+                    f,
+                    "node {} ({}) has non-finite data {}",
+                    node,
+                    label(op),
+                    data
+                )
+            }
+            GradError::NonFiniteGrad { node, op, grad } => {
+                write!(
+                    f,
+                    "node {} ({}) has non-finite grad {}",
+                    node,
+                    label(op),
+                    grad
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for GradError {}
+
+thread_local! {
+    static GRAD_ENABLED: std::cell::Cell<bool> = const { std::cell::Cell::new(true) };
+}
+
+/// Runs `f` without recording the graph: ops still compute their values but
+/// keep no parents or backward closures, so nothing is retained for
+/// `backward` (which then leaves every other node's grad alone). Meant for
+/// inference; the previous mode is restored afterwards, even on panic.
+pub fn no_grad<R>(f: impl FnOnce() -> R) -> R {
+    struct Restore(bool);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            GRAD_ENABLED.with(|g| g.set(self.0));
+        }
+    }
+    let _restore = Restore(GRAD_ENABLED.with(|g| g.replace(false)));
+    f()
+}
+
+/// False inside [`no_grad`].
+pub fn is_grad_enabled() -> bool {
+    GRAD_ENABLED.with(|g| g.get())
+}
+
+/// A shared handle to a graph node. A node keeps its parents alive, but
+/// nothing keeps a node alive except handles to it (and to its consumers), so
+/// dropping the loss after `backward` frees every intermediate node that isn't
+/// otherwise referenced. Parameters and inputs survive as long as the model
+/// or caller holds them.
 #[derive(Clone)]
 pub struct Value(pub Rc<RefCell<Data>>);
 
@@ -49,85 +175,358 @@ impl Value {
             grad: 0.0,
             parents: vec![],
             op: None,
+            requires_grad: true,
             _backward: None,
         };
-        Value(Rc::new(RefCell::new(data)))
+        Value::from_data(data)
+    }
+
+    /// Wraps a freshly built node. Under [`no_grad`] an op's node is stored
+    /// as a plain constant: no parents, no op and `requires_grad` off.
+    fn from_data(mut data: Data) -> Value {
+        if !data.parents.is_empty() && !is_grad_enabled() {
+            data.parents = vec![];
+            data.op = None;
+            data.requires_grad = false;
+        }
+        crate::pool::alloc(data)
+    }
+
+    fn set_backward(&self, backward: BackwardFn) {
+        let mut data = self.0.borrow_mut();
+        if !data.parents.is_empty() {
+            data._backward = Some(backward);
+        }
     }
 
     pub fn value(&self) -> f64 {
         self.0.borrow().data
     }
 
+    /// Overwrites this node's value. Nodes already built from it keep the
+    /// old value until the graph is rebuilt or [`relink`](Value::relink)ed.
+    pub fn set_data(&self, data: f64) {
+        self.0.borrow_mut().data = data;
+    }
+
+    /// True if both handles point at the same graph node.
+    pub fn same_node(&self, other: &Value) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// True for inputs and parameters, i.e. nodes not produced by an op.
+    /// Nodes built under [`no_grad`] are leaves too.
+    pub fn is_leaf(&self) -> bool {
+        self.0.borrow().parents.is_empty()
+    }
+
+    /// Handles to the nodes this one was computed from, in op order.
+    pub fn parents(&self) -> Vec<Value> {
+        self.0.borrow().parents.clone()
+    }
+
     pub fn tanh(&self) -> Value {
         let x = self.0.borrow().data;
         let t = x.tanh();
-        let input_node = self.clone();
         let new_data = Data {
             data: t,
             grad: 0.0,
             parents: vec![self.clone()],
             op: Some(Ops::Tanh),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
+        let out = Value::from_data(new_data);
 
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            // d/dx tanh(x) = 1 - tanh(x)^2, so the forward output is all we need
             let local_derivative = 1.0 - t * t;
-            input_node.0.borrow_mut().grad += local_derivative * out_grad;
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 
     pub fn relu(&self) -> Value {
         let x = self.0.borrow().data;
         let val = if x < 0.0 { 0.0 } else { x };
-        let input_node = self.clone();
         let new_data = Data {
             data: val,
             grad: 0.0,
             parents: vec![self.clone()],
             op: Some(Ops::Relu),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let local_derivative = if val > 0.0 { 1.0 } else { 0.0 };
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
 
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
-            let local_derivative = if x > 0.0 { 1.0 } else { 0.0 };
-            input_node.0.borrow_mut().grad += local_derivative * out_grad;
+    pub fn elu(&self, alpha: f64) -> Value {
+        let x = self.0.borrow().data;
+        let val = if x > 0.0 { x } else { alpha * (x.exp() - 1.0) };
+        let new_data = Data {
+            data: val,
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::Elu(alpha)),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            // On the negative branch d/dx alpha*(e^x - 1) = alpha*e^x = out + alpha
+            let local_derivative = if x > 0.0 { 1.0 } else { val + alpha };
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    pub fn softplus(&self) -> Value {
+        let x = self.0.borrow().data;
+        // ln(1 + e^x) = max(x, 0) + ln(1 + e^-|x|), which never overflows
+        let val = x.max(0.0) + (-x.abs()).exp().ln_1p();
+        let new_data = Data {
+            data: val,
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::Softplus),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let local_derivative = stable_sigmoid(x);
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    pub fn sigmoid(&self) -> Value {
+        let x = self.0.borrow().data;
+        let s = stable_sigmoid(x);
+        let new_data = Data {
+            data: s,
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::Sigmoid),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let local_derivative = s * (1.0 - s);
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    /// `x` clamped to `[min, max]`. The gradient is 1 strictly inside the
+    /// interval and 0 elsewhere, including at the boundaries.
+    pub fn hardtanh(&self, min: f64, max: f64) -> Value {
+        let x = self.0.borrow().data;
+        let new_data = Data {
+            data: x.clamp(min, max),
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::Hardtanh(min, max)),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let local_derivative = if x > min && x < max { 1.0 } else { 0.0 };
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    /// Piecewise-linear sigmoid, `clamp(0.2 * x + 0.5, 0, 1)`; saturated
+    /// (and gradient-free) for `|x| >= 2.5`.
+    pub fn hardsigmoid(&self) -> Value {
+        let x = self.0.borrow().data;
+        let new_data = Data {
+            data: (0.2 * x + 0.5).clamp(0.0, 1.0),
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::Hardsigmoid),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let local_derivative = if x > -2.5 && x < 2.5 { 0.2 } else { 0.0 };
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    /// Passes finite values through unchanged and replaces NaN/Inf with
+    /// `replacement`. A replaced value blocks the gradient, so one bad sample
+    /// can't poison the weights beneath it.
+    pub fn nan_to_num(&self, replacement: f64) -> Value {
+        let x = self.0.borrow().data;
+        let finite = x.is_finite();
+        let new_data = Data {
+            data: if finite { x } else { replacement },
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::NanToNum(replacement)),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            if finite {
+                parents[0].0.borrow_mut().grad += out_grad;
+            }
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    /// `|x|`, using the subgradient 0 at `x == 0`.
+    pub fn abs(&self) -> Value {
+        let x = self.0.borrow().data;
+        let new_data = Data {
+            data: x.abs(),
+            grad: 0.0,
+            parents: vec![self.clone()],
+            op: Some(Ops::Abs),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let local_derivative = if x > 0.0 {
+                1.0
+            } else if x < 0.0 {
+                -1.0
+            } else {
+                0.0
+            };
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 
     pub fn pow(&self, exponent: f64) -> Value {
         let x = self.0.borrow().data;
         let out_data = x.powf(exponent);
-        let input_node = self.clone();
         let new_data = Data {
             data: out_data,
             grad: 0.0,
             parents: vec![self.clone()],
             op: Some(Ops::Pow(exponent)),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
+        let out = Value::from_data(new_data);
 
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
             let local_derivative = exponent * x.powf(exponent - 1.0);
-            input_node.0.borrow_mut().grad += local_derivative * out_grad;
+            parents[0].0.borrow_mut().grad += local_derivative * out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 
-    pub fn backward(&self) {
+    /// `self^other` with both operands in the graph, computed as
+    /// `exp(other * ln(self))` so both receive gradients via the chain rule.
+    /// The logarithm restricts the base to positive values.
+    pub fn pow_value(&self, other: &Value) -> Value {
+        let base = self.value();
+        assert!(base > 0.0, "pow_value: base must be positive, got {}", base);
+        (&self.log() * other).exp()
+    }
+
+    /// Larger of two values; the gradient flows only to the winner (ties go to `self`).
+    pub fn max(&self, other: &Value) -> Value {
+        let left_wins = self.0.borrow().data >= other.0.borrow().data;
+        let winner = if left_wins { self } else { other };
+        let new_data = Data {
+            data: winner.0.borrow().data,
+            grad: 0.0,
+            parents: vec![self.clone(), other.clone()],
+            op: Some(Ops::Max),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+        let winner_idx = if left_wins { 0 } else { 1 };
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            parents[winner_idx].0.borrow_mut().grad += out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    /// `bias + sum(weights[i] * inputs[i])` as a single node, instead of one
+    /// multiply and one add per input. Parents are the inputs, then the
+    /// weights, then the bias.
+    pub fn linear(inputs: &[Value], weights: &[Value], bias: &Value) -> Value {
+        assert_eq!(
+            inputs.len(),
+            weights.len(),
+            "linear: got {} inputs but {} weights",
+            inputs.len(),
+            weights.len()
+        );
+        let xs: Vec<f64> = inputs.iter().map(|x| x.value()).collect();
+        let ws: Vec<f64> = weights.iter().map(|w| w.value()).collect();
+        // Same summation order as folding `b + w0*x0 + w1*x1 + ...`
+        let out_data = xs
+            .iter()
+            .zip(&ws)
+            .fold(bias.value(), |acc, (x, w)| acc + w * x);
+
+        let mut parents = inputs.to_vec();
+        parents.extend_from_slice(weights);
+        parents.push(bias.clone());
+        let new_data = Data {
+            data: out_data,
+            grad: 0.0,
+            parents,
+            op: Some(Ops::Linear),
+            requires_grad: true,
+            _backward: None,
+        };
+        let out = Value::from_data(new_data);
+
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            let n = xs.len();
+            for i in 0..n {
+                parents[i].0.borrow_mut().grad += ws[i] * out_grad;
+                parents[n + i].0.borrow_mut().grad += xs[i] * out_grad;
+            }
+            parents[2 * n].0.borrow_mut().grad += out_grad;
+        });
+        out.set_backward(backward);
+        out
+    }
+
+    /// Every node reachable from `self`, parents before the nodes built from them.
+    pub(crate) fn topo_order(&self) -> Vec<Value> {
         let mut topo = Vec::new();
         let mut visited = std::collections::HashSet::new();
 
@@ -147,65 +546,233 @@ impl Value {
         }
 
         build_topo(self, &mut visited, &mut topo);
-        self.0.borrow_mut().grad = 1.0;
+        topo
+    }
+
+    /// Recomputes every node under `self` from its parents' current data and
+    /// rebuilds its backward closure, so an existing graph picks up leaves
+    /// changed with [`set_data`](Value::set_data) without re-running the
+    /// forward pass. Interior grads are reset; leaf grads are left for the
+    /// caller to zero (e.g. `Module::zero_grad`) as usual.
+    pub fn relink(&self) {
+        for node in self.topo_order() {
+            let fresh = {
+                let data = node.0.borrow();
+                let Some(op) = &data.op else { continue };
+                let p = &data.parents;
+                match op {
+                    Ops::Add => &p[0] + &p[1],
+                    Ops::Sub => sub_node(&p[0], &p[1]),
+                    Ops::Mul => &p[0] * &p[1],
+                    Ops::Tanh => p[0].tanh(),
+                    Ops::Exp => p[0].exp(),
+                    Ops::Log => p[0].log(),
+                    Ops::Pow(e) => p[0].pow(*e),
+                    Ops::Relu => p[0].relu(),
+                    Ops::Elu(alpha) => p[0].elu(*alpha),
+                    Ops::Softplus => p[0].softplus(),
+                    Ops::Sigmoid => p[0].sigmoid(),
+                    Ops::Max => p[0].max(&p[1]),
+                    Ops::Abs => p[0].abs(),
+                    Ops::Hardtanh(min, max) => p[0].hardtanh(*min, *max),
+                    Ops::Hardsigmoid => p[0].hardsigmoid(),
+                    Ops::NanToNum(r) => p[0].nan_to_num(*r),
+                    Ops::Sum => sum_kahan(p),
+                    Ops::Prod => reduce_prod(p),
+                    Ops::Linear => {
+                        let n = (p.len() - 1) / 2;
+                        Value::linear(&p[..n], &p[n..2 * n], &p[2 * n])
+                    }
+                }
+            };
+            // `fresh` has the same parents, so its closure works for this node too
+            let fresh_backward = fresh.0.borrow_mut()._backward.take();
+            let mut data = node.0.borrow_mut();
+            data.data = fresh.value();
+            data.grad = 0.0;
+            data._backward = fresh_backward;
+        }
+    }
+
+    /// Approximate heap bytes held by the graph under `self`: each node's
+    /// allocation, its parent list and its backward closure's captures.
+    pub fn graph_memory_estimate(&self) -> usize {
+        // strong + weak counts in front of each `Rc` allocation
+        let rc_header = 2 * std::mem::size_of::<usize>();
+        self.topo_order()
+            .iter()
+            .map(|v| {
+                let data = v.0.borrow();
+                rc_header
+                    + std::mem::size_of::<RefCell<Data>>()
+                    + data.parents.capacity() * std::mem::size_of::<Value>()
+                    + data
+                        ._backward
+                        .as_ref()
+                        .map_or(0, |f| std::mem::size_of_val(&**f))
+            })
+            .sum()
+    }
+
+    /// Plain-data view of the graph: one `NodeInfo` per node (ids follow
+    /// topological order) and `(parent, child)` edges between those ids.
+    pub fn to_adjacency(&self) -> (Vec<NodeInfo>, Vec<(usize, usize)>) {
+        let topo = self.topo_order();
+        let ids: std::collections::HashMap<*const Data, usize> = topo
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.0.as_ptr() as *const Data, i))
+            .collect();
+
+        let mut nodes = Vec::with_capacity(topo.len());
+        let mut edges = vec![];
+        for (id, v) in topo.iter().enumerate() {
+            let data = v.0.borrow();
+            nodes.push(NodeInfo {
+                id,
+                data: data.data,
+                grad: data.grad,
+                op: data.op.as_ref().map(|op| format!("{:?}", op)),
+            });
+            for parent in &data.parents {
+                edges.push((ids[&(parent.0.as_ptr() as *const Data)], id));
+            }
+        }
+        (nodes, edges)
+    }
+
+    pub fn backward(&self) {
+        self.backward_from(1.0);
+    }
+
+    /// Backpropagates with the root's incoming gradient set to `seed`
+    /// instead of 1, i.e. a vector-Jacobian product for a scalar output.
+    pub fn backward_from(&self, seed: f64) {
+        let topo = self.topo_order();
+
+        // A node only needs its closure run if some leaf beneath it is trainable.
+        // Parents come before children in `topo`, so one forward sweep suffices.
+        let mut needs_grad = std::collections::HashSet::new();
+        for node in &topo {
+            let data = node.0.borrow();
+            let needed = if data.parents.is_empty() {
+                data.requires_grad
+            } else {
+                data.parents
+                    .iter()
+                    .any(|p| needs_grad.contains(&(p.0.as_ptr() as *const Data)))
+            };
+            if needed {
+                needs_grad.insert(node.0.as_ptr() as *const Data);
+            }
+        }
+
+        self.0.borrow_mut().grad = seed;
         for node in topo.iter().rev() {
-            if let Some(ref backward_fn) = node.0.borrow()._backward {
-                backward_fn();
+            if !needs_grad.contains(&(node.0.as_ptr() as *const Data)) {
+                continue;
+            }
+            let data = node.0.borrow();
+            if let Some(ref backward_fn) = data._backward {
+                backward_fn(&data.parents, data.grad);
+            }
+        }
+    }
+
+    /// Like [`backward`](Value::backward), but afterwards scans the graph for
+    /// NaN/Inf. Forward values are checked first, parents before children, so
+    /// the error names the op where the bad value first appeared; gradients
+    /// are checked from the root down for the same reason.
+    pub fn backward_checked(&self) -> Result<(), GradError> {
+        self.backward();
+        let topo = self.topo_order();
+        let op_name = |v: &Value| v.0.borrow().op.as_ref().map(|op| format!("{:?}", op));
+
+        for (node, v) in topo.iter().enumerate() {
+            let data = v.0.borrow().data;
+            if !data.is_finite() {
+                return Err(GradError::NonFiniteData {
+                    node,
+                    op: op_name(v),
+                    data,
+                });
+            }
+        }
+        for (node, v) in topo.iter().enumerate().rev() {
+            let grad = v.0.borrow().grad;
+            if !grad.is_finite() {
+                return Err(GradError::NonFiniteGrad {
+                    node,
+                    op: op_name(v),
+                    grad,
+                });
             }
         }
+        Ok(())
     }
+
     pub fn exp(&self) -> Value {
         let x = self.0.borrow().data;
         let out_data = x.exp();
-        let input_node = self.clone();
         let new_data = Data {
             data: out_data,
             grad: 0.0,
             parents: vec![self.clone()],
             op: Some(Ops::Exp),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
+        let out = Value::from_data(new_data);
 
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
-            input_node.0.borrow_mut().grad += out_data * out_grad;
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            parents[0].0.borrow_mut().grad += out_data * out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 
     pub fn log(&self) -> Value {
         let x = self.0.borrow().data;
         let out_data = x.ln();
-        let input_node = self.clone();
         let new_data = Data {
             data: out_data,
             grad: 0.0,
             parents: vec![self.clone()],
             op: Some(Ops::Log),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
+        let out = Value::from_data(new_data);
 
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
-            input_node.0.borrow_mut().grad += (1.0 / x) * out_grad;
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            parents[0].0.borrow_mut().grad += (1.0 / x) * out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 
     pub fn draw(&self) {
+        self.open_visualizer(None);
+    }
+
+    pub fn draw_depth(&self, max_levels: usize) {
+        self.open_visualizer(Some(max_levels));
+    }
+
+    fn open_visualizer(&self, max_depth: Option<usize>) {
         let value_to_draw = self.clone();
         let native_options = eframe::NativeOptions {
             event_loop_builder: Some(Box::new(|builder| {
-                #[cfg(target_os = "windows")]
+                // `draw` is called from test threads; winit refuses to build an
+                // event loop off the main thread there unless told otherwise
+                #[cfg(any(target_os = "windows", target_os = "linux"))]
                 {
                     builder.with_any_thread(true);
                 }
+                #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+                {
+                    let _ = builder;
+                }
             })),
             viewport: egui::ViewportBuilder::default().with_inner_size([800.0, 600.0]),
             ..Default::default()
@@ -217,6 +784,7 @@ impl Value {
             Box::new(|_cc| {
                 Ok(Box::new(GraphVisualizer {
                     root: value_to_draw,
+                    max_depth,
                     centered: false,
                 }))
             }),
@@ -224,6 +792,17 @@ impl Value {
     }
 
     pub fn render_node(&self, ui: &mut Ui, pos: Pos2) -> egui::Rect {
+        self.render_node_to_depth(ui, pos, None)
+    }
+
+    /// Renders this node and at most `levels_left` hops of its lineage.
+    /// Parents past the cutoff are drawn as dangling stubs.
+    pub fn render_node_to_depth(
+        &self,
+        ui: &mut Ui,
+        pos: Pos2,
+        levels_left: Option<usize>,
+    ) -> egui::Rect {
         let data = self.0.borrow();
         let box_size = Vec2::new(80.0, 50.0);
         let rect = egui::Rect::from_min_size(pos, box_size);
@@ -270,6 +849,17 @@ impl Value {
                 Ops::Log => "log".to_string(),
                 Ops::Pow(n) => format!("**{}", n),
                 Ops::Relu => "ReLU".to_string(),
+                Ops::Elu(alpha) => format!("ELU({})", alpha),
+                Ops::Softplus => "sp".to_string(),
+                Ops::Sigmoid => "σ".to_string(),
+                Ops::Max => "max".to_string(),
+                Ops::Abs => "|x|".to_string(),
+                Ops::Prod => "∏".to_string(),
+                Ops::Linear => "wx+b".to_string(),
+                Ops::Hardtanh(min, max) => format!("clamp({},{})", min, max),
+                Ops::Hardsigmoid => "hσ".to_string(),
+                Ops::NanToNum(r) => format!("nan→{}", r),
+                Ops::Sum => "Σ".to_string(),
             };
             ui.painter().text(
                 op_center,
@@ -281,13 +871,30 @@ impl Value {
 
             let mut child_y_offset = -40.0;
             for child in &data.parents {
-                let child_pos = op_center + Vec2::new(-120.0, child_y_offset - (box_size.y / 2.0));
-                let child_rect = child.render_node(ui, child_pos);
-                self.draw_arrow(
-                    ui,
-                    child_rect.right_center(),
-                    op_center - Vec2::new(op_radius, 0.0),
-                );
+                if levels_left == Some(0) {
+                    let stub_end = op_center + Vec2::new(-60.0, child_y_offset / 2.0);
+                    ui.painter().line_segment(
+                        [stub_end, op_center - Vec2::new(op_radius, 0.0)],
+                        Stroke::new(1.0, Color32::GRAY),
+                    );
+                    ui.painter().text(
+                        stub_end,
+                        egui::Align2::RIGHT_CENTER,
+                        "…",
+                        egui::FontId::proportional(14.0),
+                        Color32::GRAY,
+                    );
+                } else {
+                    let child_pos =
+                        op_center + Vec2::new(-120.0, child_y_offset - (box_size.y / 2.0));
+                    let child_rect =
+                        child.render_node_to_depth(ui, child_pos, levels_left.map(|n| n - 1));
+                    self.draw_arrow(
+                        ui,
+                        child_rect.right_center(),
+                        op_center - Vec2::new(op_radius, 0.0),
+                    );
+                }
                 child_y_offset += 80.0;
             }
         }
@@ -312,6 +919,186 @@ impl Value {
     }
 }
 
+pub(crate) fn stable_sigmoid(x: f64) -> f64 {
+    if x >= 0.0 {
+        1.0 / (1.0 + (-x).exp())
+    } else {
+        let e = x.exp();
+        e / (1.0 + e)
+    }
+}
+
+/// Softmax across a vector of values. The max is subtracted as a constant
+/// first so large logits don't overflow `exp`.
+pub fn softmax(xs: &[Value]) -> Vec<Value> {
+    let max = xs
+        .iter()
+        .map(|x| x.value())
+        .fold(f64::NEG_INFINITY, f64::max);
+    let exps: Vec<Value> = xs.iter().map(|x| (x + -max).exp()).collect();
+    let sum = exps.iter().fold(Value::new(0.0), |acc, e| &acc + e);
+    exps.iter().map(|e| e / &sum).collect()
+}
+
+/// `x_i - logsumexp(x)`, with the max subtracted as a constant first so
+/// large inputs don't overflow `exp`.
+pub fn log_softmax(xs: &[Value]) -> Vec<Value> {
+    let max = xs
+        .iter()
+        .map(|x| x.value())
+        .fold(f64::NEG_INFINITY, f64::max);
+    let sum_exp = xs
+        .iter()
+        .fold(Value::new(0.0), |acc, x| &acc + &(x + -max).exp());
+    let log_sum_exp = &sum_exp.log() + max;
+    xs.iter().map(|x| x - &log_sum_exp).collect()
+}
+
+/// `dot(a, b) / max(||a|| * ||b||, 1e-8)`. A zero vector yields a similarity
+/// of zero, and its gradient is `b / 1e-8` instead of NaN.
+pub fn cosine_similarity(a: &[Value], b: &[Value]) -> Value {
+    assert_eq!(a.len(), b.len(), "cosine_similarity: length mismatch");
+    let dot = a
+        .iter()
+        .zip(b)
+        .fold(Value::new(0.0), |acc, (ai, bi)| &acc + &(ai * bi));
+    let sq_norm = |v: &[Value]| v.iter().fold(Value::new(0.0), |acc, vi| &acc + &(vi * vi));
+    // Clamped before the one square root, whose slope at 0 is infinite
+    let denom = (&sq_norm(a) * &sq_norm(b)).max(&Value::new(1e-16)).pow(0.5);
+    &dot / &denom
+}
+
+pub fn reduce_sum(xs: &[Value]) -> Value {
+    xs.iter().fold(Value::new(0.0), |acc, x| &acc + x)
+}
+
+/// Sum of `xs` as a single node (0 for an empty slice), accumulated with
+/// Kahan compensated summation so many small terms aren't lost next to a
+/// large one. Every input gets gradient 1, as with a chain of adds.
+pub fn sum_kahan(xs: &[Value]) -> Value {
+    if xs.is_empty() {
+        return Value::new(0.0);
+    }
+    let (mut sum, mut compensation) = (0.0, 0.0);
+    for x in xs {
+        let y = x.value() - compensation;
+        let t = sum + y;
+        compensation = (t - sum) - y;
+        sum = t;
+    }
+    let new_data = Data {
+        data: sum,
+        grad: 0.0,
+        parents: xs.to_vec(),
+        op: Some(Ops::Sum),
+        requires_grad: true,
+        _backward: None,
+    };
+    let out = Value::from_data(new_data);
+
+    let backward = Box::new(|parents: &[Value], out_grad: f64| {
+        for p in parents {
+            p.0.borrow_mut().grad += out_grad;
+        }
+    });
+    out.set_backward(backward);
+    out
+}
+
+/// Product of `xs` as a single node (1 for an empty slice). Each input gets
+/// the product of all the others, computed from prefix and suffix products
+/// rather than `out / x_i`, so zeros are handled exactly.
+pub fn reduce_prod(xs: &[Value]) -> Value {
+    if xs.is_empty() {
+        return Value::new(1.0);
+    }
+    let vals: Vec<f64> = xs.iter().map(|x| x.value()).collect();
+    let new_data = Data {
+        data: vals.iter().product(),
+        grad: 0.0,
+        parents: xs.to_vec(),
+        op: Some(Ops::Prod),
+        requires_grad: true,
+        _backward: None,
+    };
+    let out = Value::from_data(new_data);
+
+    let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+        // suffix[i] = vals[i..].product()
+        let mut suffix = vec![1.0; vals.len() + 1];
+        for i in (0..vals.len()).rev() {
+            suffix[i] = suffix[i + 1] * vals[i];
+        }
+        let mut prefix = 1.0;
+        for (i, x) in parents.iter().enumerate() {
+            x.0.borrow_mut().grad += prefix * suffix[i + 1] * out_grad;
+            prefix *= vals[i];
+        }
+    });
+    out.set_backward(backward);
+    out
+}
+
+/// Largest element; the whole gradient goes to it (the first on ties).
+pub fn reduce_max(xs: &[Value]) -> Value {
+    let (first, rest) = xs.split_first().expect("reduce_max: empty slice");
+    rest.iter().fold(first.clone(), |acc, x| acc.max(x))
+}
+
+/// Smallest element; the whole gradient goes to it (the first on ties).
+pub fn reduce_min(xs: &[Value]) -> Value {
+    let negated: Vec<Value> = xs.iter().map(|x| -x).collect();
+    -&reduce_max(&negated)
+}
+
+/// Gradients of the inputs after a `backward` from a loss, i.e. the
+/// saliency map (or the direction an adversarial perturbation should take).
+/// Inputs are leaves like parameters, so they accumulate grads the same way;
+/// zero them before reusing inputs across backward passes.
+pub fn input_grads(inputs: &[Value]) -> Vec<f64> {
+    inputs.iter().map(|x| x.0.borrow().grad).collect()
+}
+
+/// Directional derivative of a scalar `output` along `direction`, i.e.
+/// `grad(output) · direction` with respect to `inputs`. Computed with one
+/// backward pass after zeroing every grad in `output`'s graph and in
+/// `inputs`, so any gradients stored there are overwritten.
+pub fn jvp(inputs: &[Value], output: &Value, direction: &[f64]) -> f64 {
+    assert_eq!(
+        inputs.len(),
+        direction.len(),
+        "jvp: got {} inputs but a {}-dimensional direction",
+        inputs.len(),
+        direction.len()
+    );
+    for v in output.topo_order().iter().chain(inputs) {
+        v.0.borrow_mut().grad = 0.0;
+    }
+    output.backward();
+    input_grads(inputs)
+        .iter()
+        .zip(direction)
+        .map(|(g, d)| g * d)
+        .sum()
+}
+
+/// Fast-gradient-sign step: fresh leaves holding `x + eps * sign(grad_x)`.
+/// Inputs with a zero gradient are left unchanged.
+pub fn perturb_inputs(inputs: &[Value], eps: f64) -> Vec<Value> {
+    inputs
+        .iter()
+        .map(|x| {
+            let data = x.0.borrow();
+            let step = if data.grad == 0.0 {
+                0.0
+            } else {
+                eps * data.grad.signum()
+            };
+            Value::new(data.data + step)
+        })
+        .collect()
+}
+
 impl Debug for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         self.0.borrow().fmt(f)
@@ -343,18 +1130,15 @@ impl Add<&Value> for &Value {
             grad: 0.0,
             parents: vec![left, right],
             op: Some(Ops::Add),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
-        let left_node = self.clone();
-        let right_node = rhs.clone();
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
-            left_node.0.borrow_mut().grad += out_grad;
-            right_node.0.borrow_mut().grad += out_grad;
+        let out = Value::from_data(new_data);
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            parents[0].0.borrow_mut().grad += out_grad;
+            parents[1].0.borrow_mut().grad += out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 }
@@ -373,6 +1157,26 @@ impl Add<&Value> for f64 {
     }
 }
 
+/// A single `Sub` node. The `-` operator builds `a + (-b)` instead; this is
+/// only needed to rebuild `Sub` nodes made directly from [`Data`].
+fn sub_node(a: &Value, b: &Value) -> Value {
+    let new_data = Data {
+        data: a.value() - b.value(),
+        grad: 0.0,
+        parents: vec![a.clone(), b.clone()],
+        op: Some(Ops::Sub),
+        requires_grad: true,
+        _backward: None,
+    };
+    let out = Value::from_data(new_data);
+    let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+        parents[0].0.borrow_mut().grad += out_grad;
+        parents[1].0.borrow_mut().grad -= out_grad;
+    });
+    out.set_backward(backward);
+    out
+}
+
 impl Sub<&Value> for &Value {
     type Output = Value;
     fn sub(self, rhs: &Value) -> Self::Output {
@@ -380,10 +1184,13 @@ impl Sub<&Value> for &Value {
     }
 }
 
+#[allow(clippy::suspicious_arithmetic_impl)]
 impl Mul<&Value> for &Value {
     type Output = Value;
     fn mul(self, rhs: &Value) -> Self::Output {
-        let product = self.0.borrow().data * rhs.0.borrow().data;
+        let l_data = self.0.borrow().data;
+        let r_data = rhs.0.borrow().data;
+        let product = l_data * r_data;
         let left = self.clone();
         let right = rhs.clone();
         let new_data = Data {
@@ -391,20 +1198,15 @@ impl Mul<&Value> for &Value {
             grad: 0.0,
             parents: vec![left, right],
             op: Some(Ops::Mul),
+            requires_grad: true,
             _backward: None,
         };
-        let out = Value(Rc::new(RefCell::new(new_data)));
-        let out_clone = out.clone();
-        let left_node = self.clone();
-        let right_node = rhs.clone();
-        let backward = Box::new(move || {
-            let out_grad = out_clone.0.borrow().grad;
-            let l_data = left_node.0.borrow().data;
-            let r_data = right_node.0.borrow().data;
-            left_node.0.borrow_mut().grad += r_data * out_grad;
-            right_node.0.borrow_mut().grad += l_data * out_grad;
+        let out = Value::from_data(new_data);
+        let backward = Box::new(move |parents: &[Value], out_grad: f64| {
+            parents[0].0.borrow_mut().grad += r_data * out_grad;
+            parents[1].0.borrow_mut().grad += l_data * out_grad;
         });
-        out.0.borrow_mut()._backward = Some(backward);
+        out.set_backward(backward);
         out
     }
 }
@@ -430,20 +1232,106 @@ impl Div<&Value> for &Value {
     }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct SGD {
-    pub params: Vec<Value>,
-    pub lr: f64,
+    /// Private so its layout always matches `lrs` and `velocity`.
+    pub(crate) groups: Vec<ParamGroup>,
+    pub momentum: f64,
+    /// Step along the lookahead `grad + momentum * v` instead of `v`.
+    pub nesterov: bool,
+    pub(crate) lrs: GroupLrs,
+    /// Momentum buffer in parameter order; empty until the first step with
+    /// nonzero `momentum`.
+    pub(crate) velocity: Vec<f64>,
+    pub(crate) steps: usize,
+    pub(crate) accumulation: Accumulation,
 }
 
 impl SGD {
     pub fn new(params: Vec<Value>, lr: f64) -> Self {
-        Self { params, lr }
+        Self::from_groups(vec![ParamGroup::new(params, lr)])
+    }
+
+    /// One optimizer over several parameter groups, each with its own learning rate.
+    pub fn with_param_groups(groups: Vec<(Vec<Value>, f64)>) -> Self {
+        Self::from_groups(
+            groups
+                .into_iter()
+                .map(|(params, lr)| ParamGroup::new(params, lr))
+                .collect(),
+        )
+    }
+
+    /// One optimizer over several [`ParamGroup`]s, each with its own learning
+    /// rate and weight decay.
+    pub fn from_groups(groups: Vec<ParamGroup>) -> Self {
+        Self {
+            lrs: GroupLrs::new(&groups),
+            groups,
+            momentum: 0.0,
+            nesterov: false,
+            velocity: vec![],
+            steps: 0,
+            accumulation: Accumulation::default(),
+        }
+    }
+
+    pub fn builder() -> SgdBuilder {
+        SgdBuilder::default()
+    }
+
+    pub fn groups(&self) -> &[ParamGroup] {
+        &self.groups
+    }
+
+    /// Sets group `i`'s learning rate; later
+    /// [`set_lr`](crate::optim::Optimizer::set_lr) calls scale it from there.
+    pub fn set_group_lr(&mut self, i: usize, lr: f64) {
+        self.lrs.set_group_lr(&mut self.groups, i, lr);
+    }
+
+    /// The momentum buffer, or the zeros it starts as if not allocated yet.
+    pub(crate) fn velocity_or_zeros(&self) -> Vec<f64> {
+        if self.velocity.is_empty() {
+            vec![0.0; group_params(&self.groups).count()]
+        } else {
+            self.velocity.clone()
+        }
     }
 
-    pub fn step(&self) {
-        for p in &self.params {
+    pub fn step(&mut self) {
+        if !self.accumulation.ready(&self.groups) {
+            return;
+        }
+        self.steps += 1;
+        if self.momentum != 0.0 && self.velocity.is_empty() {
+            self.velocity = vec![0.0; group_params(&self.groups).count()];
+        }
+        let slots = self
+            .groups
+            .iter()
+            .flat_map(|g| g.params.iter().map(move |p| (g, p)));
+        for (i, (group, p)) in slots.enumerate() {
             let mut data = p.0.borrow_mut();
-            data.data -= self.lr * data.grad;
+            if !data.requires_grad {
+                continue;
+            }
+            let g = data.grad + group.weight_decay * data.data;
+            // Without a buffer momentum is 0, so v is just g
+            let v = match self.velocity.get_mut(i) {
+                Some(v) => {
+                    *v = self.momentum * *v + g;
+                    *v
+                }
+                None => g,
+            };
+            let update = if self.nesterov {
+                g + self.momentum * v
+            } else {
+                v
+            };
+            data.data -= group.lr * update;
         }
+        self.accumulation.finish(&self.groups);
     }
 }
@@ -0,0 +1,227 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+
+use crate::engine::{Data, NodeInfo, Ops, Value};
+
+impl Value {
+    /// Writes a self-contained HTML page showing the graph with a
+    /// force-directed layout. Nodes can be dragged; hovering shows data/grad.
+    pub fn to_html(&self, path: &str) -> io::Result<()> {
+        let (nodes, edges) = self.to_adjacency();
+        let page = HTML_TEMPLATE.replace("__GRAPH__", &graph_json(&nodes, &edges));
+        fs::write(path, page)
+    }
+
+    /// ONNX-inspired description of the graph: nodes in topological order,
+    /// each with an `op_type`, its `inputs` by id (in argument order), any
+    /// op `attributes` (e.g. `pow`'s exponent) and its current `value`.
+    /// Leaves have `op_type` `"Leaf"`; `output` is the id of `self`.
+    pub fn to_graph_json(&self) -> String {
+        let topo = self.topo_order();
+        let ids: std::collections::HashMap<*const Data, usize> = topo
+            .iter()
+            .enumerate()
+            .map(|(i, v)| (v.0.as_ptr() as *const Data, i))
+            .collect();
+        let nodes: Vec<String> = topo
+            .iter()
+            .enumerate()
+            .map(|(id, v)| {
+                let data = v.0.borrow();
+                let inputs: Vec<String> = data
+                    .parents
+                    .iter()
+                    .map(|p| ids[&(p.0.as_ptr() as *const Data)].to_string())
+                    .collect();
+                let (op_type, attributes) = match &data.op {
+                    Some(op) => (op_name(op), op_attributes(op)),
+                    None => ("Leaf", vec![]),
+                };
+                let attributes: Vec<String> = attributes
+                    .iter()
+                    .map(|(k, x)| format!("{}:{}", json_string(k), json_number(*x)))
+                    .collect();
+                format!(
+                    "{{\"id\":{},\"op_type\":{},\"inputs\":[{}],\"attributes\":{{{}}},\"value\":{}}}",
+                    id,
+                    json_string(op_type),
+                    inputs.join(","),
+                    attributes.join(","),
+                    json_number(data.data)
+                )
+            })
+            .collect();
+        format!(
+            "{{\"nodes\":[{}],\"output\":{}}}",
+            nodes.join(","),
+            topo.len() - 1
+        )
+    }
+}
+
+fn op_name(op: &Ops) -> &'static str {
+    match op {
+        Ops::Add => "Add",
+        Ops::Sub => "Sub",
+        Ops::Mul => "Mul",
+        Ops::Tanh => "Tanh",
+        Ops::Exp => "Exp",
+        Ops::Log => "Log",
+        Ops::Pow(_) => "Pow",
+        Ops::Relu => "Relu",
+        Ops::Elu(_) => "Elu",
+        Ops::Softplus => "Softplus",
+        Ops::Sigmoid => "Sigmoid",
+        Ops::Max => "Max",
+        Ops::Abs => "Abs",
+        Ops::Prod => "Prod",
+        Ops::Linear => "Linear",
+        Ops::Hardtanh(..) => "Hardtanh",
+        Ops::Hardsigmoid => "Hardsigmoid",
+        Ops::NanToNum(_) => "NanToNum",
+        Ops::Sum => "Sum",
+    }
+}
+
+fn op_attributes(op: &Ops) -> Vec<(&'static str, f64)> {
+    match op {
+        Ops::Pow(e) => vec![("exponent", *e)],
+        Ops::Elu(alpha) => vec![("alpha", *alpha)],
+        Ops::Hardtanh(min, max) => vec![("min", *min), ("max", *max)],
+        Ops::NanToNum(r) => vec![("replacement", *r)],
+        _ => vec![],
+    }
+}
+
+pub(crate) fn json_number(x: f64) -> String {
+    if x.is_finite() {
+        format!("{}", x)
+    } else {
+        "null".to_string()
+    }
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn graph_json(nodes: &[NodeInfo], edges: &[(usize, usize)]) -> String {
+    let nodes: Vec<String> = nodes
+        .iter()
+        .map(|n| {
+            format!(
+                "{{\"id\":{},\"data\":{},\"grad\":{},\"op\":{}}}",
+                n.id,
+                json_number(n.data),
+                json_number(n.grad),
+                n.op.as_deref().map_or("null".to_string(), json_string)
+            )
+        })
+        .collect();
+    let edges: Vec<String> = edges
+        .iter()
+        .map(|(from, to)| format!("[{},{}]", from, to))
+        .collect();
+    format!(
+        "{{\"nodes\":[{}],\"edges\":[{}]}}",
+        nodes.join(","),
+        edges.join(",")
+    )
+}
+
+const HTML_TEMPLATE: &str = r##"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Value Graph</title>
+<style>
+  body { margin: 0; background: #1e1e1e; color: #fff; font-family: sans-serif; }
+  svg { width: 100vw; height: 100vh; }
+  line { stroke: #888; }
+  circle { stroke: #fff; cursor: grab; }
+  text { font-size: 11px; fill: #fff; pointer-events: none; }
+</style>
+</head>
+<body>
+<svg id="graph"></svg>
+<script>
+const graph = __GRAPH__;
+const svg = document.getElementById("graph");
+const W = window.innerWidth, H = window.innerHeight;
+const ns = "http://www.w3.org/2000/svg";
+const nodes = graph.nodes.map(n => Object.assign(n, {
+  x: W / 2 + (Math.random() - 0.5) * W / 2,
+  y: H / 2 + (Math.random() - 0.5) * H / 2,
+  vx: 0, vy: 0,
+}));
+
+function tick() {
+  for (const a of nodes) {
+    for (const b of nodes) {
+      if (a === b) continue;
+      const dx = a.x - b.x, dy = a.y - b.y;
+      const d2 = Math.max(dx * dx + dy * dy, 1);
+      a.vx += 800 * dx / d2; a.vy += 800 * dy / d2;
+    }
+    a.vx += (W / 2 - a.x) * 0.002; a.vy += (H / 2 - a.y) * 0.002;
+  }
+  for (const [s, t] of graph.edges) {
+    const a = nodes[s], b = nodes[t];
+    const dx = b.x - a.x, dy = b.y - a.y;
+    const d = Math.max(Math.hypot(dx, dy), 1), f = (d - 80) * 0.02;
+    a.vx += f * dx / d; a.vy += f * dy / d;
+    b.vx -= f * dx / d; b.vy -= f * dy / d;
+  }
+  for (const n of nodes) {
+    if (n === dragged) continue;
+    n.x += n.vx *= 0.6; n.y += n.vy *= 0.6;
+  }
+}
+
+const lines = graph.edges.map(() => svg.appendChild(document.createElementNS(ns, "line")));
+const groups = nodes.map(n => {
+  const g = svg.appendChild(document.createElementNS(ns, "g"));
+  const c = g.appendChild(document.createElementNS(ns, "circle"));
+  c.setAttribute("r", n.op ? 16 : 10);
+  c.setAttribute("fill", n.op ? "#465" : "#333");
+  const label = g.appendChild(document.createElementNS(ns, "text"));
+  label.textContent = n.op ? n.op : (n.data === null ? "NaN" : n.data.toFixed(2));
+  label.setAttribute("text-anchor", "middle");
+  label.setAttribute("dy", "4");
+  const title = g.appendChild(document.createElementNS(ns, "title"));
+  title.textContent = `#${n.id} data: ${n.data} grad: ${n.grad}`;
+  c.addEventListener("mousedown", () => dragged = n);
+  return g;
+});
+
+let dragged = null;
+svg.addEventListener("mousemove", e => { if (dragged) { dragged.x = e.clientX; dragged.y = e.clientY; } });
+window.addEventListener("mouseup", () => dragged = null);
+
+function render() {
+  tick();
+  graph.edges.forEach(([s, t], i) => {
+    lines[i].setAttribute("x1", nodes[s].x); lines[i].setAttribute("y1", nodes[s].y);
+    lines[i].setAttribute("x2", nodes[t].x); lines[i].setAttribute("y2", nodes[t].y);
+  });
+  nodes.forEach((n, i) => groups[i].setAttribute("transform", `translate(${n.x},${n.y})`));
+  requestAnimationFrame(render);
+}
+render();
+</script>
+</body>
+</html>
+"##;
@@ -0,0 +1,137 @@
+use rand::Rng;
+use rand_distr::StandardNormal;
+
+use crate::nn::Activation;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+    Uniform,
+    Normal,
+}
+
+/// Weight initialization scheme.
+///
+/// | Scheme            | Uniform range                         | Normal std                      |
+/// | :---------------- | :------------------------------------ | :------------------------------ |
+/// | `Xavier`          | `±sqrt(6 / (fan_in + fan_out))`       | `sqrt(2 / (fan_in + fan_out))`  |
+/// | `He`              | `±sqrt(6 / fan_in)`                   | `sqrt(2 / fan_in)`              |
+///
+/// Both forms of a scheme have the same variance; only the shape differs.
+/// `Uniform`, `Normal` and `Constant` ignore the fan sizes; `Constant` is
+/// handy for tests whose layer outputs should be computable by hand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Init {
+    Uniform {
+        lo: f64,
+        hi: f64,
+    },
+    Normal {
+        mean: f64,
+        std: f64,
+    },
+    Constant(f64),
+    /// A (semi-)orthogonal weight matrix scaled by `gain`: orthonormal rows
+    /// when a layer has no more outputs than inputs, orthonormal columns
+    /// otherwise. Layers draw the whole matrix at once; sampling a single
+    /// value (e.g. as a bias init) gives a 1x1 orthogonal matrix, `±gain`.
+    Orthogonal {
+        gain: f64,
+    },
+    Xavier(Distribution),
+    He(Distribution),
+}
+
+impl Default for Init {
+    fn default() -> Self {
+        Init::Uniform { lo: -1.0, hi: 1.0 }
+    }
+}
+
+impl Init {
+    /// The scheme used when none is given: `He(Normal)` for relu layers,
+    /// `Uniform(-1, 1)` otherwise.
+    pub fn default_for(act: Activation) -> Init {
+        match act {
+            Activation::Relu => Init::He(Distribution::Normal),
+            _ => Init::default(),
+        }
+    }
+
+    /// Panics on bounds `sample` can't draw from. Constructors call this
+    /// once up front rather than on every sample.
+    pub(crate) fn validate(&self) {
+        if let Init::Uniform { lo, hi } = *self {
+            assert!(
+                lo < hi,
+                "Init::Uniform: lo ({}) must be less than hi ({})",
+                lo,
+                hi
+            );
+        }
+    }
+
+    pub fn sample(&self, rng: &mut impl Rng, fan_in: u64, fan_out: u64) -> f64 {
+        match *self {
+            Init::Uniform { lo, hi } => rng.random_range(lo..hi),
+            Init::Normal { mean, std } => mean + std * standard_normal(rng),
+            Init::Constant(c) => c,
+            Init::Orthogonal { gain } => orthogonal(rng, 1, 1, gain)[0][0],
+            Init::Xavier(dist) => {
+                let var = 2.0 / (fan_in + fan_out) as f64;
+                sample_with_variance(rng, dist, var)
+            }
+            Init::He(dist) => {
+                let var = 2.0 / fan_in as f64;
+                sample_with_variance(rng, dist, var)
+            }
+        }
+    }
+}
+
+fn sample_with_variance(rng: &mut impl Rng, dist: Distribution, var: f64) -> f64 {
+    match dist {
+        // U(-a, a) has variance a^2 / 3
+        Distribution::Uniform => {
+            let bound = (3.0 * var).sqrt();
+            rng.random_range(-bound..bound)
+        }
+        Distribution::Normal => var.sqrt() * standard_normal(rng),
+    }
+}
+
+/// `rows x cols` Gaussian matrix orthonormalized with Gram-Schmidt along the
+/// shorter side, then scaled by `gain`.
+pub(crate) fn orthogonal(rng: &mut impl Rng, rows: usize, cols: usize, gain: f64) -> Vec<Vec<f64>> {
+    let (n, len) = (rows.min(cols), rows.max(cols));
+    let mut basis: Vec<Vec<f64>> = Vec::with_capacity(n);
+    while basis.len() < n {
+        let mut v: Vec<f64> = (0..len).map(|_| standard_normal(rng)).collect();
+        // Two passes of modified Gram-Schmidt keep the result orthogonal to
+        // machine precision.
+        for _ in 0..2 {
+            for b in &basis {
+                let dot: f64 = v.iter().zip(b).map(|(x, y)| x * y).sum();
+                v.iter_mut().zip(b).for_each(|(x, y)| *x -= dot * y);
+            }
+        }
+        let norm = v.iter().map(|x| x * x).sum::<f64>().sqrt();
+        // A draw (almost surely never) in the span of the others: try again
+        if norm > 1e-10 {
+            basis.push(v.into_iter().map(|x| x / norm).collect());
+        }
+    }
+    if rows <= cols {
+        basis
+            .iter()
+            .map(|r| r.iter().map(|x| gain * x).collect())
+            .collect()
+    } else {
+        (0..rows)
+            .map(|i| basis.iter().map(|c| gain * c[i]).collect())
+            .collect()
+    }
+}
+
+pub(crate) fn standard_normal(rng: &mut impl Rng) -> f64 {
+    rng.sample(StandardNormal)
+}
@@ -1,12 +1,27 @@
-mod engine;
-mod nn;
-mod visualizer;
+#[cfg(feature = "ndarray")]
+pub mod array;
+pub mod builder;
+pub mod checkpoint;
+pub mod complex_step;
+pub mod data;
+pub mod engine;
+pub mod export;
+pub mod init;
+pub mod metrics;
+pub mod nn;
+pub mod optim;
+pub mod plot;
+pub mod pool;
+pub mod scheduler;
+pub mod tape;
+pub mod trainer;
+pub mod visualizer;
 
 #[cfg(test)]
 mod tests {
     use crate::{
-        engine::*,
-        nn::{Layer, MLP, Module, Neuron},
+        builder::*, checkpoint::*, complex_step::*, data::*, engine::*, init::*, nn::*, optim::*,
+        plot::*, pool::*, scheduler::*, tape::*, trainer::*,
     };
 
     #[test]
@@ -60,6 +75,17 @@ mod tests {
         o.draw();
     }
 
+    #[test]
+    fn test_draw_depth() {
+        let x = Value::new(0.5);
+        let mut out = x.clone();
+        for _ in 0..10 {
+            out = (&out * &x).tanh();
+        }
+        out.backward();
+        out.draw_depth(3);
+    }
+
     #[test]
     fn test_backward() {
         let x = Value::new(2.0);
@@ -97,7 +123,7 @@ mod tests {
     fn test_mlp_and_params() {
         let x = vec![Value::new(2.0), Value::new(3.0)];
         let model = MLP::new(2, vec![4, 4, 1]);
-        let out = model.call(x);
+        let out = model.call(&x);
 
         assert_eq!(out.len(), 1);
 
@@ -111,39 +137,3303 @@ mod tests {
     }
 
     #[test]
-    fn test_grad_check() {
-        let x = Value::new(1.234);
-        let y = Value::new(-2.345);
+    fn test_forward_through_dyn_module() {
+        let x = vec![Value::new(0.5), Value::new(-1.5)];
+        let models: Vec<Box<dyn Module>> = vec![
+            Box::new(Neuron::new(2, true)),
+            Box::new(Layer::new(2, 3, true)),
+            Box::new(MLP::new(2, vec![4, 2])),
+        ];
+        let outs: Vec<usize> = models.iter().map(|m| m.forward(&x).len()).collect();
+        assert_eq!(outs, vec![1, 3, 2]);
 
-        let f = (&x * &y).tanh();
-        f.backward();
+        let n = Neuron::new(2, true);
+        let l = Layer::new(2, 3, true);
+        let m = MLP::new(2, vec![4, 2]);
+        let as_dyn = |module: &dyn Module| -> Vec<f64> {
+            module.forward(&x).iter().map(|v| v.value()).collect()
+        };
+        assert_eq!(as_dyn(&n), vec![n.call(&x).value()]);
+        assert_eq!(
+            as_dyn(&l),
+            l.call(&x).iter().map(|v| v.value()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            as_dyn(&m),
+            m.call(&x).iter().map(|v| v.value()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_mlp_reuses_input_slice() {
+        let model = MLP::new(2, vec![3, 1]);
+        let mut optimizer = SGD::new(model.parameters(), 0.05);
+        let x = [Value::new(0.5), Value::new(-1.0)];
+
+        let mut losses = vec![];
+        for _ in 0..20 {
+            let diff = &model.call(&x)[0] - &Value::new(1.0);
+            let loss = &diff * &diff;
+            model.zero_grad();
+            loss.backward();
+            optimizer.step();
+            losses.push(loss.value());
+        }
+
+        assert_eq!(x[0].value(), 0.5);
+        assert_eq!(x[1].value(), -1.0);
+        assert!(losses.last().unwrap() < &losses[0]);
+    }
+
+    #[test]
+    fn test_grad_and_param_norm() {
+        let n = Neuron::new(3, true);
+        for (p, (data, grad)) in
+            n.parameters()
+                .iter()
+                .zip([(1.0, 2.0), (-2.0, 0.0), (2.0, -1.0), (4.0, 2.0)])
+        {
+            let mut d = p.0.borrow_mut();
+            d.data = data;
+            d.grad = grad;
+        }
+        assert_eq!(n.grad_norm(), 3.0);
+        assert_eq!(n.param_norm(), 5.0);
+
+        n.zero_grad();
+        assert_eq!(n.grad_norm(), 0.0);
+    }
+
+    #[test]
+    fn test_freeze_first_layer() {
+        let model = MLP::new(2, vec![4, 1]);
+        let mut optimizer = SGD::new(model.parameters(), 0.05);
+        model.layer(0).unwrap().freeze();
+
+        let snapshot =
+            |l: &Layer| -> Vec<f64> { l.parameters().iter().map(|p| p.value()).collect() };
+        let layer0_before = snapshot(model.layer(0).unwrap());
+        let layer1_before = snapshot(model.layer(1).unwrap());
+
+        let x = [Value::new(0.5), Value::new(-1.0)];
+        let mut losses = vec![];
+        for _ in 0..50 {
+            let diff = &model.call(&x)[0] - &Value::new(2.0);
+            let loss = &diff * &diff;
+            model.zero_grad();
+            loss.backward();
+            optimizer.step();
+            losses.push(loss.value());
+        }
+
+        assert_eq!(snapshot(model.layer(0).unwrap()), layer0_before);
+        assert_ne!(snapshot(model.layer(1).unwrap()), layer1_before);
+        assert!(losses.last().unwrap() < &losses[0]);
+
+        model.unfreeze();
+        assert!(
+            model
+                .parameters()
+                .iter()
+                .all(|p| p.0.borrow().requires_grad)
+        );
+    }
+
+    #[test]
+    fn test_backward_skips_frozen_subgraph() {
+        let a = Value::new(2.0);
+        let b = Value::new(3.0);
+        a.0.borrow_mut().requires_grad = false;
+        b.0.borrow_mut().requires_grad = false;
+        let c = Value::new(4.0);
+
+        let ab = &a * &b;
+        let out = &ab * &c;
+        out.backward();
+
+        assert_eq!(c.0.borrow().grad, 6.0);
+        assert_eq!(ab.0.borrow().grad, 4.0);
+        assert_eq!(a.0.borrow().grad, 0.0);
+        assert_eq!(b.0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_init_distribution_statistics() {
+        let (nin, nout) = (256, 256);
+        let cases = [
+            (Init::Xavier(Distribution::Uniform), 2.0 / 512.0),
+            (Init::Xavier(Distribution::Normal), 2.0 / 512.0),
+            (Init::He(Distribution::Uniform), 2.0 / 256.0),
+            (Init::He(Distribution::Normal), 2.0 / 256.0),
+        ];
+        for (init, expected_var) in cases {
+            let layer = Layer::with_init(nin, nout, Activation::Tanh, init);
+            // Biases are zero-initialized, so keep only the weights
+            let weights: Vec<f64> = layer
+                .parameters()
+                .chunks(nin as usize + 1)
+                .flat_map(|n| n[..nin as usize].iter().map(|w| w.value()))
+                .collect();
+            let count = weights.len() as f64;
+            let mean = weights.iter().sum::<f64>() / count;
+            let var = weights.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / count;
+
+            assert!(mean.abs() < 0.01, "{:?} mean {}", init, mean);
+            assert!(
+                (var - expected_var).abs() / expected_var < 0.05,
+                "{:?} var {} expected {}",
+                init,
+                var,
+                expected_var
+            );
+        }
+    }
+
+    #[test]
+    fn test_xavier_avoids_tanh_saturation() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(3);
+        let saturated_fraction = |init: Init, rng: &mut StdRng| {
+            let hidden = Layer::with_init_rng(64, 64, Activation::Tanh, init, rng);
+            let mut saturated = 0;
+            for _ in 0..20 {
+                let x: Vec<Value> = (0..64)
+                    .map(|_| Value::new(rng.random_range(-1.0..1.0)))
+                    .collect();
+                saturated += hidden
+                    .call(&x)
+                    .iter()
+                    .filter(|h| h.value().abs() >= 0.99)
+                    .count();
+            }
+            saturated as f64 / (20.0 * 64.0)
+        };
+
+        let xavier = saturated_fraction(Init::Xavier(Distribution::Uniform), &mut rng);
+        let uniform = saturated_fraction(Init::default(), &mut rng);
+        assert!(xavier < 0.05, "xavier saturated {}", xavier);
+        assert!(uniform > 0.2, "uniform saturated {}", uniform);
+
+        let mlp = MLP::new_with_init_rng(
+            64,
+            vec![64, 1],
+            Activation::Tanh,
+            Activation::Linear,
+            Init::Xavier(Distribution::Normal),
+            &mut rng,
+        );
+        assert_eq!(mlp.parameters().len(), 64 * 65 + 65);
+    }
+
+    #[test]
+    fn test_he_init_is_relu_default() {
+        let layer = Layer::with_activation(200, 200, Activation::Relu);
+        let weights: Vec<f64> = layer
+            .neurons()
+            .iter()
+            .flat_map(|n| n.weights().iter().map(|w| w.value()))
+            .collect();
+        let var = weights.iter().map(|w| w * w).sum::<f64>() / weights.len() as f64;
+        assert!(
+            (var - 2.0 / 200.0).abs() / (2.0 / 200.0) < 0.05,
+            "var {}",
+            var
+        );
+
+        let final_loss = |init: Option<Init>| {
+            let mut builder = MLP::builder().input(2);
+            for _ in 0..4 {
+                builder = builder.dense(24, Activation::Relu);
+            }
+            builder = builder.dense(1, Activation::Linear).seed(4);
+            if let Some(init) = init {
+                builder = builder.init(init);
+            }
+            let model = builder.build().unwrap();
+            let mut optimizer = SGD::new(model.parameters(), 0.01);
+
+            let xs: Vec<[f64; 2]> = (0..6)
+                .map(|i| {
+                    let a = i as f64 * std::f64::consts::TAU / 6.0;
+                    [a.cos(), a.sin()]
+                })
+                .collect();
+            let ys: Vec<f64> = xs.iter().map(|x| x[0] + 0.5 * x[1]).collect();
+            let mut loss = Value::new(0.0);
+            for _ in 0..30 {
+                let preds: Vec<Value> = xs
+                    .iter()
+                    .map(|x| model.forward(&[Value::new(x[0]), Value::new(x[1])])[0].clone())
+                    .collect();
+                loss = losses::mse(&preds, &ys);
+                model.zero_grad();
+                loss.backward();
+                optimizer.step();
+            }
+            loss.value()
+        };
+        let he = final_loss(None);
+        let uniform = final_loss(Some(Init::default()));
+        assert!(he < uniform, "he {} uniform {}", he, uniform);
+    }
+
+    #[test]
+    fn test_constant_and_normal_init() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let layer = Layer::with_init(3, 2, Activation::Linear, Init::Constant(0.5));
+        let out = layer.call(&[Value::new(1.0), Value::new(-2.0), Value::new(4.0)]);
+        // 0.5 * (1 - 2 + 4) + 0 bias
+        assert_eq!(
+            out.iter().map(|o| o.value()).collect::<Vec<_>>(),
+            [1.5, 1.5]
+        );
+
+        let normal = Layer::with_init_rng(
+            200,
+            100,
+            Activation::Linear,
+            Init::Normal {
+                mean: 1.0,
+                std: 0.5,
+            },
+            &mut StdRng::seed_from_u64(1),
+        );
+        let weights: Vec<f64> = normal
+            .neurons()
+            .iter()
+            .flat_map(|n| n.weights().iter().map(|w| w.value()))
+            .collect();
+        let mean = weights.iter().sum::<f64>() / weights.len() as f64;
+        let var = weights.iter().map(|w| (w - mean).powi(2)).sum::<f64>() / weights.len() as f64;
+        assert!((mean - 1.0).abs() < 0.01 && (var - 0.25).abs() < 0.0125);
+
+        for init in [
+            Init::Uniform { lo: -0.1, hi: 0.3 },
+            Init::Normal {
+                mean: 0.0,
+                std: 2.0,
+            },
+            Init::Constant(-0.7),
+            Init::Xavier(Distribution::Normal),
+            Init::He(Distribution::Uniform),
+        ] {
+            let built = MLP::builder()
+                .input(4)
+                .dense(3, Activation::Tanh)
+                .init(init)
+                .seed(5)
+                .build()
+                .unwrap();
+            let direct =
+                Layer::with_init_rng(4, 3, Activation::Tanh, init, &mut StdRng::seed_from_u64(5));
+            let values =
+                |m: &dyn Module| -> Vec<f64> { m.parameters().iter().map(|p| p.value()).collect() };
+            assert_eq!(values(&built), values(&direct), "{:?}", init);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Init::Uniform: lo (0.5) must be less than hi (0.5)")]
+    fn test_uniform_init_rejects_empty_range() {
+        Layer::with_init(2, 2, Activation::Tanh, Init::Uniform { lo: 0.5, hi: 0.5 });
+    }
+
+    #[test]
+    fn test_neuron_bias_init() {
+        let n = Neuron::with_inits(3, 1, Activation::Relu, Init::default(), Init::Constant(0.1));
+        assert_eq!(n.bias().value(), 0.1);
+        assert!(n.parameters().iter().any(|p| p.same_node(n.bias())));
+        assert_eq!(Neuron::new(3, true).bias().value(), 0.0);
+    }
+
+    #[test]
+    fn test_layer_bias_init() {
+        let layer = Layer::with_inits(2, 3, Activation::Tanh, Init::default(), Init::Constant(1.0));
+        let biases: Vec<&Value> = layer.neurons().iter().map(|n| n.bias()).collect();
+        assert!(biases.iter().all(|b| b.value() == 1.0));
+        let params = layer.parameters();
+        assert!(biases.iter().all(|b| params.iter().any(|p| p.same_node(b))));
+
+        let model = MLP::builder()
+            .input(2)
+            .dense(3, Activation::Tanh)
+            .bias_init(Init::Constant(1.0))
+            .build()
+            .unwrap();
+        let named = model.named_parameters();
+        let bias_values = || -> Vec<f64> {
+            named
+                .iter()
+                .filter(|(name, _)| name.ends_with(".b"))
+                .map(|(_, b)| b.value())
+                .collect()
+        };
+        assert_eq!(bias_values(), [1.0; 3]);
+
+        let mut optimizer = SGD::new(model.parameters(), 0.1);
+        let out = model.forward(&[Value::new(0.5), Value::new(-0.5)]);
+        losses::mse(&out, &[0.0; 3]).backward();
+        optimizer.step();
+        assert!(bias_values().iter().all(|&b| b < 1.0));
+    }
+
+    #[test]
+    fn test_init_from_fn() {
+        let layer = Layer::from_fn(2, 3, Activation::Linear, |_, i| i as f64 / 10.0);
+        for n in layer.neurons() {
+            assert_eq!(
+                n.weights().iter().map(|w| w.value()).collect::<Vec<_>>(),
+                [0.0, 0.1]
+            );
+            assert_eq!(n.bias().value(), 0.2);
+        }
+
+        let model = MLP::from_fn(
+            2,
+            vec![4, 4, 1],
+            Activation::Tanh,
+            Activation::Linear,
+            |l, j, i| ((l * 17 + j * 5 + i * 3) as f64).sin() * 0.8,
+        );
+        let mut optimizer = SGD::new(model.parameters(), 0.05);
+        let inputs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let targets = [0.0, 1.0, 1.0, 0.0];
+        let predict = |x: &[f64; 2]| model.call(&[Value::new(x[0]), Value::new(x[1])])[0].clone();
+        for _ in 0..300 {
+            let preds: Vec<Value> = inputs.iter().map(predict).collect();
+            let loss = losses::mse(&preds, &targets);
+            model.zero_grad();
+            loss.backward();
+            optimizer.step();
+        }
+        for (x, y) in inputs.iter().zip(targets) {
+            assert!((predict(x).value() - y).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn test_orthogonal_init() {
+        let matrix = |layer: &Layer| -> Vec<Vec<f64>> {
+            layer
+                .neurons()
+                .iter()
+                .map(|n| n.weights().iter().map(|w| w.value()).collect())
+                .collect()
+        };
+        // max |A·Aᵀ - gain²·I| over the rows of `a`
+        let gram_error = |a: &[Vec<f64>], gain: f64| {
+            let mut worst: f64 = 0.0;
+            for (i, ri) in a.iter().enumerate() {
+                for (j, rj) in a.iter().enumerate() {
+                    let dot: f64 = ri.iter().zip(rj).map(|(x, y)| x * y).sum();
+                    let target = if i == j { gain * gain } else { 0.0 };
+                    worst = worst.max((dot - target).abs());
+                }
+            }
+            worst
+        };
+        let transpose = |a: &[Vec<f64>]| -> Vec<Vec<f64>> {
+            (0..a[0].len())
+                .map(|j| a.iter().map(|r| r[j]).collect())
+                .collect()
+        };
+
+        let square = Layer::with_init(8, 8, Activation::Tanh, Init::Orthogonal { gain: 1.0 });
+        let w = matrix(&square);
+        assert!(gram_error(&w, 1.0) < 1e-6);
+        assert!(gram_error(&transpose(&w), 1.0) < 1e-6);
+        // the neurons' weights are the layer's parameters, not copies
+        let params = square.parameters();
+        for (j, n) in square.neurons().iter().enumerate() {
+            for (i, wi) in n.weights().iter().enumerate() {
+                assert!(params[j * 9 + i].same_node(wi));
+            }
+        }
+
+        let wide = Layer::with_init(6, 3, Activation::Tanh, Init::Orthogonal { gain: 2.0 });
+        assert!(gram_error(&matrix(&wide), 2.0) < 1e-6);
+        let tall = Layer::with_init(3, 6, Activation::Tanh, Init::Orthogonal { gain: 2.0 });
+        assert!(gram_error(&transpose(&matrix(&tall)), 2.0) < 1e-6);
+    }
+
+    #[test]
+    fn test_tied_weights() {
+        let encoder = Layer::new(2, 3, true);
+        let enc_w: Vec<Vec<Value>> = encoder
+            .parameters()
+            .chunks(3)
+            .map(|n| n[..2].to_vec())
+            .collect();
+        // Decoder uses the transpose of the encoder's weight matrix
+        let dec_w: Vec<Vec<Value>> = (0..2)
+            .map(|i| enc_w.iter().map(|row| row[i].clone()).collect())
+            .collect();
+        let decoder = Layer::from_weights(
+            dec_w,
+            vec![Value::new(0.0), Value::new(0.0)],
+            Activation::Linear,
+        );
+        let model = MLP::from_layers(vec![encoder, decoder]);
+        assert_eq!(model.parameters().len(), 3 * 3 + 2);
+
+        let x = [Value::new(0.5), Value::new(-0.25)];
+        let out = model.call(&x);
+        let loss = &(&out[0] * &out[0]) + &(&out[1] * &out[1]);
+        model.zero_grad();
+        loss.backward();
+
+        let shared = &enc_w[1][0];
+        let (before, grad) = (shared.value(), shared.0.borrow().grad);
+        SGD::new(model.parameters(), 0.1).step();
+        assert_eq!(shared.value(), before - 0.1 * grad);
+    }
+
+    #[test]
+    fn test_adam_reset() {
+        let p = Value::new(1.0);
+        let mut adam = Adam::new(vec![p.clone()], 0.01);
+        let first_step = |adam: &mut Adam| {
+            let before = p.value();
+            p.0.borrow_mut().grad = 0.5;
+            adam.step();
+            before - p.value()
+        };
+
+        let fresh = first_step(&mut adam);
+        p.0.borrow_mut().grad = -3.0;
+        adam.step();
+        adam.step();
+        assert_eq!(adam.step_count(), 3);
+
+        adam.reset();
+        assert_eq!(adam.step_count(), 0);
+        assert!((first_step(&mut adam) - fresh).abs() < 1e-12);
+        assert_eq!(adam.step_count(), 1);
+    }
+
+    #[test]
+    fn test_builder() {
+        let model = MLP::builder()
+            .input(2)
+            .dense(4, Activation::Tanh)
+            .dense(4, Activation::Tanh)
+            .dense(1, Activation::Linear)
+            .build()
+            .unwrap();
+        assert_eq!(model.parameters().len(), 37);
+        assert_eq!(model.forward(&[Value::new(1.0), Value::new(2.0)]).len(), 1);
+
+        assert_eq!(
+            MLP::builder()
+                .dense(4, Activation::Relu)
+                .input(2)
+                .build()
+                .err(),
+            Some(BuildError::MissingInput)
+        );
+        assert_eq!(
+            MLP::builder()
+                .input(2)
+                .dense(0, Activation::Relu)
+                .build()
+                .err(),
+            Some(BuildError::ZeroWidth)
+        );
+        assert_eq!(
+            MLP::builder().input(2).build().err(),
+            Some(BuildError::NoLayers)
+        );
+
+        let seeded = || {
+            MLP::builder()
+                .input(2)
+                .dense(4, Activation::Relu)
+                .dropout(0.1)
+                .dense(1, Activation::Linear)
+                .seed(42)
+                .build()
+                .unwrap()
+        };
+        let values =
+            |m: &dyn Module| -> Vec<f64> { m.parameters().iter().map(|p| p.value()).collect() };
+        assert_eq!(values(&seeded()), values(&seeded()));
+    }
+
+    #[test]
+    fn test_dropout() {
+        let dropout = Dropout::new_seeded(0.5, 7);
+        let x: Vec<Value> = (0..1000).map(|_| Value::new(1.0)).collect();
+        let out = dropout.forward(&x);
+        let dropped = out.iter().filter(|v| v.value() == 0.0).count();
+        assert!(dropped > 400 && dropped < 600);
+        assert!(out.iter().all(|v| v.value() == 0.0 || v.value() == 2.0));
+
+        dropout.set_training(false);
+        assert!(dropout.forward(&x).iter().all(|v| v.value() == 1.0));
+    }
+
+    #[test]
+    fn test_seeded_dropout_is_reproducible() {
+        let x: Vec<Value> = (0..64).map(|_| Value::new(1.0)).collect();
+        let masks = |d: &Dropout| -> Vec<Vec<f64>> {
+            (0..3)
+                .map(|_| d.forward(&x).iter().map(|v| v.value()).collect())
+                .collect()
+        };
+        let a = masks(&Dropout::new_seeded(0.3, 42));
+        assert_eq!(a, masks(&Dropout::new_seeded(0.3, 42)));
+        // successive calls still draw fresh masks
+        assert_ne!(a[0], a[1]);
+    }
+
+    #[test]
+    fn test_backward_uses_construction_time_values() {
+        let a = Value::new(2.0);
+        let b = Value::new(3.0);
+        let ab = &a * &b;
+        let t = b.tanh();
+        let r = a.relu();
+        let out = &(&ab + &t) + &r;
+
+        a.0.borrow_mut().data = -5.0;
+        b.0.borrow_mut().data = 100.0;
+        out.backward();
+
+        assert_eq!(a.0.borrow().grad, 3.0 + 1.0);
+        let expected_b = 2.0 + (1.0 - 3.0_f64.tanh().powi(2));
+        assert!((b.0.borrow().grad - expected_b).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_prelu_learns_slope() {
+        let prelu = PRelu::new(0.1);
+        let mut optimizer = SGD::new(prelu.parameters(), 0.05);
+        // Target is a leaky relu with slope 0.5
+        let xs = [-2.0, -1.0, -0.5, 0.5, 1.0, 2.0];
+        for _ in 0..50 {
+            let mut loss = Value::new(0.0);
+            for &x in &xs {
+                let target = if x > 0.0 { x } else { 0.5 * x };
+                let diff = &prelu.forward(&[Value::new(x)])[0] - &Value::new(target);
+                loss = &loss + &(&diff * &diff);
+            }
+            prelu.zero_grad();
+            loss.backward();
+            optimizer.step();
+        }
+        let a = prelu.parameters()[0].value();
+        assert!(a > 0.4 && a < 0.6, "slope {}", a);
 
+        // Slope gradient against finite differences at x = -1.5
+        let f = |slope: f64| PRelu::new(slope).forward(&[Value::new(-1.5)])[0].value();
+        let prelu = PRelu::new(0.3);
+        prelu.forward(&[Value::new(-1.5)])[0].backward();
         let eps = 1e-6;
-        let x_plus = Value::new(1.234 + eps);
-        let x_minus = Value::new(1.234 - eps);
+        let numerical = (f(0.3 + eps) - f(0.3 - eps)) / (2.0 * eps);
+        assert!((numerical - prelu.parameters()[0].0.borrow().grad).abs() < 1e-6);
+    }
 
-        let f_plus = (&x_plus * &y).tanh().value();
-        let f_minus = (&x_minus * &y).tanh().value();
+    #[test]
+    fn test_optimizer_builders() {
+        let p = Value::new(1.0);
+        let sgd = SGD::builder()
+            .lr(0.1)
+            .momentum(0.9)
+            .weight_decay(0.01)
+            .build(vec![p.clone()]);
+        assert_eq!(
+            (sgd.lr(), sgd.momentum, sgd.groups()[0].weight_decay),
+            (0.1, 0.9, 0.01)
+        );
 
-        let numerical = (f_plus - f_minus) / (2.0 * eps);
-        let autodiff = x.0.borrow().grad;
+        // v1 = g, v2 = 0.9 * v1 + g
+        let mut sgd = SGD::builder().lr(0.1).momentum(0.9).build(vec![p.clone()]);
+        p.0.borrow_mut().grad = 1.0;
+        sgd.step();
+        sgd.step();
+        assert!((p.value() - (1.0 - 0.1 * 1.0 - 0.1 * 1.9)).abs() < 1e-12);
 
-        assert!((numerical - autodiff).abs() < 1e-3);
+        let adam = Adam::builder()
+            .lr(0.01)
+            .betas(0.8, 0.99)
+            .eps(1e-6)
+            .build(vec![p]);
+        assert_eq!(
+            (adam.lr(), adam.beta1, adam.beta2, adam.eps),
+            (0.01, 0.8, 0.99, 1e-6)
+        );
     }
 
     #[test]
-    fn test_xor_training_showcase() {
-        let model = MLP::new(2, vec![4, 4, 1]);
-        let optimizer = SGD::new(model.parameters(), 0.1);
+    fn test_maxout() {
+        assert_eq!(
+            MaxoutLayer::new(3, 2, 4).parameters().len(),
+            2 * 4 * (3 + 1)
+        );
+
+        let piece = |w: [f64; 2], b: f64| {
+            Neuron::from_weights(
+                w.iter().map(|&wi| Value::new(wi)).collect(),
+                Value::new(b),
+                Activation::Linear,
+            )
+        };
+        let neuron = MaxoutNeuron::from_pieces(vec![
+            piece([1.0, 0.0], 0.0),
+            piece([0.0, 1.0], 0.5),
+            piece([-1.0, -1.0], 0.0),
+        ]);
+        let x = [Value::new(1.0), Value::new(2.0)];
+        // Pieces evaluate to 1.0, 2.5 and -3.0
+        let out = neuron.call(&x);
+        assert_eq!(out.value(), 2.5);
+
+        out.backward();
+        let grads: Vec<Vec<f64>> = neuron
+            .pieces()
+            .iter()
+            .map(|p| p.parameters().iter().map(|v| v.0.borrow().grad).collect())
+            .collect();
+        assert_eq!(grads, vec![vec![0.0; 3], vec![1.0, 2.0, 1.0], vec![0.0; 3]]);
+    }
+
+    #[test]
+    fn test_to_adjacency() {
+        let a = Value::new(2.0);
+        let b = Value::new(-3.0);
+        let c = Value::new(10.0);
+        let d = &(&a * &b) + &c;
+        d.backward();
+
+        let (nodes, edges) = d.to_adjacency();
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(edges.len(), 4);
+
+        let ops: Vec<Option<&str>> = nodes.iter().map(|n| n.op.as_deref()).collect();
+        assert_eq!(ops, vec![None, None, Some("Mul"), None, Some("Add")]);
+        assert_eq!(edges, vec![(0, 2), (1, 2), (2, 4), (3, 4)]);
+        assert_eq!(nodes[4].data, 4.0);
+        assert_eq!(nodes[0].grad, -3.0);
+    }
+
+    #[test]
+    fn test_gaussian_noise() {
+        let noise = GaussianNoise::with_seed(0.5, 3);
+        let x: Vec<Value> = (0..5000).map(|i| Value::new(i as f64 * 0.001)).collect();
+
+        let out = noise.forward(&x);
+        let deltas: Vec<f64> = out
+            .iter()
+            .zip(&x)
+            .map(|(o, i)| o.value() - i.value())
+            .collect();
+        let mean = deltas.iter().sum::<f64>() / deltas.len() as f64;
+        let var = deltas.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / deltas.len() as f64;
+        assert!(mean.abs() < 0.05);
+        assert!((var - 0.25).abs() < 0.025);
+
+        let loss = out.iter().fold(Value::new(0.0), |acc, o| &acc + &(o * 3.0));
+        loss.backward();
+        assert!(x.iter().all(|xi| xi.0.borrow().grad == 3.0));
+
+        noise.set_training(false);
+        let out = noise.forward(&x);
+        assert!(out.iter().zip(&x).all(|(o, i)| o.value() == i.value()));
+    }
+
+    #[test]
+    fn test_cosine_similarity_grad_check() {
+        let a0 = [1.0, -2.0, 0.5];
+        let b0 = [0.3, 0.8, -1.2];
+        let a: Vec<Value> = a0.iter().map(|&v| Value::new(v)).collect();
+        let b: Vec<Value> = b0.iter().map(|&v| Value::new(v)).collect();
+        let sim = cosine_similarity(&a, &b);
+        sim.backward();
+
+        let f = |a: &[f64], b: &[f64]| {
+            let to_values = |v: &[f64]| v.iter().map(|&x| Value::new(x)).collect::<Vec<_>>();
+            cosine_similarity(&to_values(a), &to_values(b)).value()
+        };
+        let eps = 1e-6;
+        for i in 0..3 {
+            let (mut plus, mut minus) = (a0, a0);
+            plus[i] += eps;
+            minus[i] -= eps;
+            let numerical = (f(&plus, &b0) - f(&minus, &b0)) / (2.0 * eps);
+            assert!((numerical - a[i].0.borrow().grad).abs() < 1e-6);
+
+            let (mut plus, mut minus) = (b0, b0);
+            plus[i] += eps;
+            minus[i] -= eps;
+            let numerical = (f(&a0, &plus) - f(&a0, &minus)) / (2.0 * eps);
+            assert!((numerical - b[i].0.borrow().grad).abs() < 1e-6);
+        }
+
+        let zero = [Value::new(0.0), Value::new(0.0), Value::new(0.0)];
+        let sim = cosine_similarity(&zero, &b);
+        sim.backward();
+        assert_eq!(sim.value(), 0.0);
+        // the clamped denominator gives b / 1e-8, not b / (1e-12 |b|)
+        for (z, bi) in zero.iter().zip(b0) {
+            let grad = z.0.borrow().grad;
+            assert!((grad - bi * 1e8).abs() < 1e-3);
+            assert!(grad.abs() <= 1.2e8);
+        }
+    }
+
+    #[test]
+    fn test_mlp_accessors() {
+        let model = MLP::new(3, vec![4, 2]);
+        let count: usize = model
+            .layers()
+            .iter()
+            .flat_map(|l| l.neurons())
+            .map(|n| n.weights().len() + 1)
+            .sum();
+        assert_eq!(count, model.parameters().len());
+        assert!(model.layer(2).is_none());
+
+        // Layer 1, neuron 1, weight 2 comes after layer 0 (4 * 4 params)
+        // and neuron 0 of layer 1 (5 params)
+        let w = &model.layer(1).unwrap().neurons()[1].weights()[2];
+        assert!(std::rc::Rc::ptr_eq(&w.0, &model.parameters()[16 + 5 + 2].0));
+        let b = model.layer(1).unwrap().neurons()[1].bias();
+        assert!(std::rc::Rc::ptr_eq(&b.0, &model.parameters()[16 + 5 + 4].0));
+    }
+
+    #[test]
+    fn test_param_diff() {
+        let model = MLP::new(2, vec![3, 1]);
+        let mut optimizer = SGD::new(model.parameters(), 0.1);
+        let out = &model.call(&[Value::new(0.5), Value::new(-1.0)])[0];
+        model.zero_grad();
+        out.backward();
+
+        let before = model.parameters_snapshot();
+        optimizer.step();
+        let diff = param_diff(&before, &model.parameters_snapshot());
+
+        for (d, p) in diff.iter().zip(model.parameters()) {
+            assert!((d - (-0.1 * p.0.borrow().grad)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_pow_value() {
+        let a = Value::new(2.0);
+        let b = Value::new(3.0);
+        let c = a.pow_value(&b);
+        c.backward();
+
+        assert!((c.value() - 8.0).abs() < 1e-12);
+        assert!((a.0.borrow().grad - 12.0).abs() < 1e-9);
+        assert!((b.0.borrow().grad - 8.0 * 2.0_f64.ln()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_checkpoint_round_trip() {
+        let data = [([0.5, -1.0], 1.0), ([-0.3, 0.8], -1.0), ([1.2, 0.1], 0.5)];
+        let train_step = |model: &MLP, opt: &mut Adam| {
+            let preds: Vec<Value> = data
+                .iter()
+                .map(|(x, _)| model.call(&[Value::new(x[0]), Value::new(x[1])])[0].clone())
+                .collect();
+            let targets: Vec<f64> = data.iter().map(|(_, y)| *y).collect();
+            model.zero_grad();
+            losses::mse(&preds, &targets).backward();
+            opt.step();
+        };
+        let values = |m: &MLP| -> Vec<f64> { m.parameters().iter().map(|p| p.value()).collect() };
+
+        let model = MLP::new_seeded(2, vec![3, 1], 1);
+        let mut opt = Adam::new(model.parameters(), 0.01);
+        for _ in 0..5 {
+            train_step(&model, &mut opt);
+        }
+        let path = std::env::temp_dir().join("autodiff_rs_test_checkpoint.txt");
+        let path = path.to_str().unwrap();
+        save_checkpoint(path, &model, &opt).unwrap();
+        train_step(&model, &mut opt);
+
+        let resumed = MLP::new_seeded(2, vec![3, 1], 2);
+        let mut resumed_opt = Adam::new(resumed.parameters(), 0.01);
+        load_checkpoint(path, &resumed, &mut resumed_opt).unwrap();
+        assert_eq!(resumed_opt.step_count(), 5);
+        train_step(&resumed, &mut resumed_opt);
+        assert_eq!(values(&resumed), values(&model));
+
+        // a differently shaped model is rejected without being touched
+        let other = MLP::new_seeded(2, vec![4, 1], 3);
+        let before = values(&other);
+        let mut other_opt = Adam::new(other.parameters(), 0.01);
+        let err = load_checkpoint(path, &other, &mut other_opt).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(values(&other), before);
+
+        // every record is required, so a file missing the lr line is rejected
+        let text = std::fs::read_to_string(path).unwrap();
+        let without_lr: String = text
+            .lines()
+            .filter(|l| !l.starts_with("lr "))
+            .map(|l| format!("{}\n", l))
+            .collect();
+        std::fs::write(path, without_lr).unwrap();
+        let err = load_checkpoint(path, &resumed, &mut resumed_opt).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_plot_loss() {
+        let history: Vec<f64> = (0..50).map(|i| 1.0 / (1.0 + i as f64)).collect();
+        let path = std::env::temp_dir().join("autodiff_rs_test_loss.png");
+        plot_loss(&history, path.to_str().unwrap()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(bytes.len() > 100);
+        assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        // read the axis labels back out of the image, glyph by glyph
+        let mut decoder = png::Decoder::new(std::io::Cursor::new(bytes))
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; decoder.output_buffer_size().unwrap()];
+        decoder.next_frame(&mut pixels).unwrap();
+        let width = decoder.info().width as usize;
+        let read = |x: usize, y: usize, len: usize| -> String {
+            (0..len)
+                .map(|i| {
+                    let bits: Vec<u8> = (0..5)
+                        .map(|row| {
+                            (0..3).fold(0, |acc, col| {
+                                let px = x + i * 4 * crate::plot::SCALE + col * crate::plot::SCALE;
+                                let dark =
+                                    pixels[3 * ((y + row * crate::plot::SCALE) * width + px)] < 128;
+                                acc | (u8::from(dark) << (2 - col))
+                            })
+                        })
+                        .collect();
+                    let matches: Vec<char> = "0123456789.-EPOCHLSARMTGD|"
+                        .chars()
+                        .filter(|&c| crate::plot::glyph(c)[..] == bits[..])
+                        .collect();
+                    assert_eq!(matches.len(), 1, "glyph {} is ambiguous: {:?}", i, matches);
+                    matches[0]
+                })
+                .collect()
+        };
+        assert_eq!(read(8, 8, 4), "LOSS");
+        // centred under the 80..620 plot area, 18px above the bottom edge
+        assert_eq!(
+            read((80 + 620 - 5 * 4 * crate::plot::SCALE) / 2, 400 - 18, 5),
+            "EPOCH"
+        );
+    }
+
+    #[test]
+    fn test_plot_grads() {
+        let model = MLP::new(2, vec![3, 1]);
+        let out = model.call(&[Value::new(0.5), Value::new(-1.0)]);
+        out[0].backward();
+        let path = std::env::temp_dir().join("autodiff_rs_test_grads.png");
+        model.plot_grads(path.to_str().unwrap()).unwrap();
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(bytes.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        // a non-finite gradient must not stop the chart from being drawn
+        model.parameters()[0].0.borrow_mut().grad = f64::NAN;
+        model.plot_grads(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_to_graph_json() {
+        let x = Value::new(3.0);
+        let y = &x.pow(2.0) * &Value::new(0.5);
+        let json = y.to_graph_json();
+        assert_eq!(
+            json,
+            "{\"nodes\":[\
+             {\"id\":0,\"op_type\":\"Leaf\",\"inputs\":[],\"attributes\":{},\"value\":3},\
+             {\"id\":1,\"op_type\":\"Pow\",\"inputs\":[0],\"attributes\":{\"exponent\":2},\"value\":9},\
+             {\"id\":2,\"op_type\":\"Leaf\",\"inputs\":[],\"attributes\":{},\"value\":0.5},\
+             {\"id\":3,\"op_type\":\"Mul\",\"inputs\":[1,2],\"attributes\":{},\"value\":4.5}\
+             ],\"output\":3}"
+        );
+    }
+
+    #[test]
+    fn test_optimizer_state_resume_is_bit_exact() {
+        let xs = [[0.3, -0.7], [-1.2, 0.5], [0.9, 0.1]];
+        let ys = [0.4, -0.6, 0.2];
+        let train = |model: &MLP, opt: &mut dyn Optimizer, steps: usize| {
+            for _ in 0..steps {
+                opt.zero_grad();
+                let preds: Vec<Value> = xs
+                    .iter()
+                    .map(|x| model.call(&x.map(Value::new))[0].clone())
+                    .collect();
+                losses::mse(&preds, &ys).backward();
+                opt.step();
+            }
+        };
+        type MakeOpt = fn(&MLP) -> Box<dyn Optimizer>;
+        let make: [MakeOpt; 2] = [
+            |m| Box::new(SGD::builder().lr(0.05).momentum(0.9).build(m.parameters())),
+            |m| Box::new(Adam::new(m.parameters(), 0.01)),
+        ];
+        for make_opt in make {
+            let model = MLP::new_seeded(2, vec![4, 1], 11);
+            let mut opt = make_opt(&model);
+            train(&model, opt.as_mut(), 50);
+            let (weights, state) = (model.state(), opt.state());
+            train(&model, opt.as_mut(), 50);
+
+            let resumed = MLP::new_seeded(2, vec![4, 1], 12);
+            let mut resumed_opt = make_opt(&resumed);
+            resumed_opt.set_lr(1.0);
+            resumed.load_state(&weights).unwrap();
+            resumed_opt.load_state(&state).unwrap();
+            assert_eq!(resumed_opt.lr(), opt.lr());
+            train(&resumed, resumed_opt.as_mut(), 50);
+            assert_eq!(resumed.state(), model.state());
+        }
+
+        let small = MLP::new_seeded(2, vec![3, 1], 11);
+        let mut adam = Adam::new(small.parameters(), 0.01);
+        let big = MLP::new_seeded(2, vec![4, 1], 11);
+        let before = adam.state();
+        let err = adam.load_state(&Adam::new(big.parameters(), 0.01).state());
+        assert_eq!(
+            err,
+            Err(OptimizerStateError::LengthMismatch {
+                buffer: "m".to_string(),
+                expected: 13,
+                got: 17
+            })
+        );
+        assert_eq!(adam.state(), before);
+        let sgd_state = SGD::new(small.parameters(), 0.1).state();
+        assert!(matches!(
+            adam.load_state(&sgd_state),
+            Err(OptimizerStateError::BufferMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_to_html() {
+        let a = Value::new(2.0);
+        let b = Value::new(-3.0);
+        let c = &(&a * &b) + &Value::new(10.0);
+        c.backward();
+
+        let path = std::env::temp_dir().join("autodiff_rs_test_graph.html");
+        c.to_html(path.to_str().unwrap()).unwrap();
+        let html = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        for id in 0..5 {
+            assert!(html.contains(&format!("{{\"id\":{},", id)));
+        }
+        assert!(!html.contains("\"id\":5,"));
+        assert!(html.contains("\"op\":\"Mul\""));
+        assert!(html.contains("\"edges\":[[0,2],[1,2],[2,4],[3,4]]"));
+    }
+
+    #[test]
+    fn test_clip_grad_value() {
+        let params: Vec<Value> = [5.0, -0.3, -7.0, 1.0]
+            .iter()
+            .map(|&g| {
+                let p = Value::new(0.0);
+                p.0.borrow_mut().grad = g;
+                p
+            })
+            .collect();
+        clip_grad_value(&params, 1.0);
+        let grads: Vec<f64> = params.iter().map(|p| p.0.borrow().grad).collect();
+        assert_eq!(grads, vec![1.0, -0.3, -1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_clip_grad_value_keeps_training_finite() {
+        // f(w) = w^4 from w = 3 with lr 0.1 overshoots further every step
+        let run = |clip: Option<f64>| {
+            let w = Value::new(3.0);
+            let mut sgd = SGD::new(vec![w.clone()], 0.1);
+            for _ in 0..50 {
+                w.0.borrow_mut().grad = 0.0;
+                w.pow(4.0).backward();
+                if let Some(c) = clip {
+                    crate::engine::clip_grad_value(std::slice::from_ref(&w), c);
+                }
+                sgd.step();
+            }
+            w.value()
+        };
+        assert!(run(None).is_nan());
+        let clipped = run(Some(1.0));
+        assert!(
+            clipped.is_finite() && clipped.abs() < 0.5,
+            "w = {}",
+            clipped
+        );
+    }
+
+    #[test]
+    fn test_clip_grad_norm() {
+        let with_grads = |grads: &[f64]| -> Vec<Value> {
+            grads
+                .iter()
+                .map(|&g| {
+                    let p = Value::new(0.0);
+                    p.0.borrow_mut().grad = g;
+                    p
+                })
+                .collect()
+        };
+        let norm = |params: &[Value]| {
+            params
+                .iter()
+                .map(|p| p.0.borrow().grad.powi(2))
+                .sum::<f64>()
+                .sqrt()
+        };
+
+        // norm 13 > 5: rescaled to 5, ratios kept
+        let params = with_grads(&[3.0, -4.0, 12.0]);
+        assert_eq!(clip_grad_norm(&params, 5.0), 13.0);
+        assert!((norm(&params) - 5.0).abs() < 1e-9);
+        let grads: Vec<f64> = params.iter().map(|p| p.0.borrow().grad).collect();
+        assert!((grads[1] / grads[0] - -4.0 / 3.0).abs() < 1e-12);
+        assert!((grads[2] / grads[0] - 4.0).abs() < 1e-12);
+
+        // already within bounds: untouched
+        let params = with_grads(&[0.3, -0.4]);
+        assert!((clip_grad_norm(&params, 1.0) - 0.5).abs() < 1e-12);
+        let grads: Vec<f64> = params.iter().map(|p| p.0.borrow().grad).collect();
+        assert_eq!(grads, vec![0.3, -0.4]);
+    }
+
+    #[test]
+    fn test_microbatch_trainer_matches_full_batch() {
+        let xs: Vec<[f64; 2]> = (0..12)
+            .map(|i| [(i as f64 * 0.7).sin(), (i as f64 * 1.3).cos()])
+            .collect();
+        let ys: Vec<f64> = xs.iter().map(|x| x[0] * x[1]).collect();
+        let loss_on = |model: &MLP, range: std::ops::Range<usize>| {
+            let preds: Vec<Value> = range
+                .clone()
+                .map(|i| model.call(&xs[i].map(Value::new))[0].clone())
+                .collect();
+            losses::mse(&preds, &ys[range])
+        };
+
+        let full = MLP::new_seeded(2, vec![3, 1], 4);
+        loss_on(&full, 0..12).backward();
+        let full_grads: Vec<f64> = full
+            .parameters()
+            .iter()
+            .map(|p| p.0.borrow().grad)
+            .collect();
+        SGD::new(full.parameters(), 0.1).step();
+
+        // K = 3 microbatches of M = 4
+        let micro = MLP::new_seeded(2, vec![3, 1], 4);
+        let mut trainer = MicrobatchTrainer::new(SGD::new(micro.parameters(), 0.1), 3);
+        for k in 0..3 {
+            let stepped = trainer.backward(&loss_on(&micro, 4 * k..4 * k + 4));
+            assert_eq!(stepped, k == 2);
+            if k == 1 {
+                assert_eq!(trainer.pending(), 2);
+            }
+        }
+        for ((p, q), g) in micro
+            .parameters()
+            .iter()
+            .zip(full.parameters())
+            .zip(full_grads)
+        {
+            assert!((p.value() - q.value()).abs() < 1e-12);
+            assert!(g != 0.0);
+            assert_eq!(p.0.borrow().grad, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_add_grad_noise() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let noisy = |seed: u64| {
+            let params: Vec<Value> = (0..4).map(|i| Value::new(i as f64)).collect();
+            for p in &params {
+                p.0.borrow_mut().grad = 1.0;
+            }
+            add_grad_noise(&params, 0.1, &mut StdRng::seed_from_u64(seed));
+            params.iter().map(|p| p.0.borrow().grad).collect::<Vec<_>>()
+        };
+        let grads = noisy(3);
+        assert!(grads.iter().all(|&g| g != 1.0 && (g - 1.0).abs() < 1.0));
+        assert_eq!(grads, noisy(3));
+        assert_ne!(grads, noisy(4));
+    }
+
+    #[test]
+    fn test_generic_optimizer() {
+        fn train_one_epoch(opt: &mut dyn Optimizer, model: &MLP, data: &[([f64; 2], f64)]) -> f64 {
+            let mut total = 0.0;
+            for (x, y) in data {
+                opt.zero_grad();
+                let loss = losses::mse(&model.call(&x.map(Value::new)), &[*y]);
+                loss.backward();
+                opt.step();
+                total += loss.value();
+            }
+            total / data.len() as f64
+        }
+        let data = [([0.5, -0.2], 0.3), ([-1.0, 0.4], -0.5), ([0.1, 0.9], 0.8)];
+
+        let sgd_model = MLP::new_seeded(2, vec![4, 1], 1);
+        let adam_model = MLP::new_seeded(2, vec![4, 1], 1);
+        let mut optimizers: Vec<(Box<dyn Optimizer>, &MLP)> = vec![
+            (Box::new(SGD::new(sgd_model.parameters(), 0.05)), &sgd_model),
+            (
+                Box::new(Adam::new(adam_model.parameters(), 0.01)),
+                &adam_model,
+            ),
+        ];
+        for (opt, model) in optimizers.iter_mut() {
+            let first = train_one_epoch(opt.as_mut(), model, &data);
+            let mut last = first;
+            for _ in 0..20 {
+                last = train_one_epoch(opt.as_mut(), model, &data);
+            }
+            assert!(last < first, "{} -> {}", first, last);
+            let lr = opt.lr();
+            opt.set_lr(lr / 2.0);
+            assert_eq!(opt.lr(), lr / 2.0);
+        }
+    }
+
+    #[test]
+    fn test_lbfgs_rosenbrock() {
+        let rosenbrock = |a: &Value, b: &Value| {
+            let one_minus_a = &Value::new(1.0) - a;
+            let curve = b - &a.pow(2.0);
+            &one_minus_a.pow(2.0) + &(&curve.pow(2.0) * 100.0)
+        };
+        let run = |history_size: usize, iters: usize| {
+            let (a, b) = (Value::new(-1.2), Value::new(1.0));
+            let mut lbfgs = LBFGS::new(vec![a.clone(), b.clone()], history_size, 1.0);
+            let mut losses = vec![];
+            for _ in 0..iters {
+                losses.push(lbfgs.step(|| {
+                    a.0.borrow_mut().grad = 0.0;
+                    b.0.borrow_mut().grad = 0.0;
+                    let loss = rosenbrock(&a, &b);
+                    loss.backward();
+                    loss.value()
+                }));
+            }
+            (losses, a.value(), b.value())
+        };
+
+        let (losses, a, b) = run(10, 100);
+        assert!(*losses.last().unwrap() < 1e-6, "loss = {:?}", losses.last());
+        assert!((a - 1.0).abs() < 1e-2 && (b - 1.0).abs() < 1e-2);
+
+        // no history: line-searched gradient descent, slow but monotone
+        let (losses, _, _) = run(0, 100);
+        assert!(losses.windows(2).all(|w| w[1] <= w[0]));
+        assert!(*losses.last().unwrap() < losses[0]);
+    }
+
+    #[test]
+    fn test_finite_grad_guard_skips_non_finite() {
+        // d/dx ln(x) at 0 is inf; the other two grads stay finite
+        let (x, y, z) = (Value::new(0.0), Value::new(2.0), Value::new(-1.0));
+        let backward = || {
+            for p in [&x, &y, &z] {
+                p.0.borrow_mut().grad = 0.0;
+            }
+            (&(&x.log() + &(&y * &z)) + &z).backward();
+        };
+        backward();
+        assert!(x.0.borrow().grad.is_infinite());
+
+        let params = vec![x.clone(), y.clone(), z.clone()];
+        let mut guard = FiniteGradGuard::new(SGD::new(params.clone(), 0.1));
+        assert_eq!(guard.try_step(), Ok(1));
+        assert_eq!(x.value(), 0.0);
+        assert!((y.value() - 2.1).abs() < 1e-12);
+        assert!((z.value() - -1.3).abs() < 1e-12);
+        assert_eq!(guard.skipped(), 1);
+
+        backward();
+        let mut strict = FiniteGradGuard::new(SGD::new(params, 0.1)).strict(true);
+        let err = strict.try_step().unwrap_err();
+        assert_eq!(
+            err,
+            StepError::NonFiniteGrad {
+                index: 0,
+                value: f64::INFINITY
+            }
+        );
+        assert!((y.value() - 2.1).abs() < 1e-12);
+        // the trait's step can't fail, so it skips even in strict mode
+        Optimizer::step(&mut strict);
+        assert_eq!(x.value(), 0.0);
+        assert!((y.value() - 2.23).abs() < 1e-12);
+        assert_eq!(strict.parameters().len(), 3);
+    }
+
+    #[test]
+    fn test_accumulation_steps_average_microbatches() {
+        type MakeOpt = fn(Vec<Value>) -> Box<dyn Optimizer>;
+        let makers: [MakeOpt; 2] = [
+            |p| Box::new(SGD::builder().lr(0.1).momentum(0.9).build(p)),
+            |p| Box::new(Adam::new(p, 0.1)),
+        ];
+        for make in makers {
+            let (w, b) = (Value::new(0.5), Value::new(-0.3));
+            let loss = |x: f64| (&(&w * &Value::new(x)) + &b).pow(2.0);
+
+            // four identical microbatches, the plain zero_grad/backward/step loop
+            let mut opt = make(vec![w.clone(), b.clone()]);
+            opt.set_accumulation_steps(4);
+            for i in 0..4 {
+                opt.zero_grad();
+                loss(2.0).backward();
+                opt.step();
+                if i < 3 {
+                    assert_eq!((w.value(), b.value()), (0.5, -0.3));
+                }
+            }
+            assert_eq!(opt.step_count(), 1);
+            assert_eq!(w.0.borrow().grad, 0.0);
+            let accumulated = (w.value(), b.value());
+
+            // one ordinary step on the (identical) averaged gradient
+            w.0.borrow_mut().data = 0.5;
+            b.0.borrow_mut().data = -0.3;
+            let mut reference = make(vec![w.clone(), b.clone()]);
+            reference.zero_grad();
+            loss(2.0).backward();
+            reference.step();
+            assert!((w.value() - accumulated.0).abs() < 1e-12);
+            assert!((b.value() - accumulated.1).abs() < 1e-12);
+        }
+
+        // a part-way group survives a state round trip
+        let w = Value::new(1.0);
+        let mut opt = SGD::new(vec![w.clone()], 0.1);
+        opt.set_accumulation_steps(3);
+        w.0.borrow_mut().grad = 3.0;
+        opt.step();
+        opt.step();
+        let state = opt.state();
+        assert_eq!(state.pending, 2);
+        let mut resumed = SGD::new(vec![w.clone()], 0.1);
+        resumed.set_accumulation_steps(3);
+        resumed.load_state(&state).unwrap();
+        resumed.step();
+        assert_eq!(resumed.step_count(), 1);
+        assert!((w.value() - 0.9).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_cross_entropy_eps_keeps_log_finite() {
+        // a manual softmax whose target probability underflows to exactly 0
+        let logits = [Value::new(0.0), Value::new(800.0)];
+        let probs = softmax(&logits);
+        assert_eq!(probs[0].value(), 0.0);
+        assert!(probs[0].log().value().is_infinite());
+
+        let loss = losses::cross_entropy_eps(&probs, 0, 1e-7);
+        loss.backward();
+        assert!((loss.value() - -(1e-7f64).ln()).abs() < 1e-9);
+        assert!(logits.iter().all(|l| l.0.borrow().grad.is_finite()));
+
+        // inside the clamp it is the ordinary -ln(p)
+        let p = Value::new(1e-3);
+        let loss = losses::cross_entropy_eps(&[p.clone(), Value::new(0.999)], 0, 1e-7);
+        loss.backward();
+        assert!((loss.value() - -(1e-3f64).ln()).abs() < 1e-12);
+        assert!((p.0.borrow().grad - -1e3).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_ema_weights() {
+        let w = Value::new(1.0);
+        let mut ema = Ema::new(vec![w.clone()], 0.0);
+        w.0.borrow_mut().data = 0.3;
+        ema.update();
+        assert_eq!(ema.shadow(), &[0.3]);
+
+        let mut ema = Ema::new(vec![w.clone()], 0.5);
+        w.0.borrow_mut().data = 0.1 + 0.2;
+        ema.update();
+        let live = w.value();
+        ema.apply();
+        assert_eq!(w.value(), 0.5 * 0.3 + 0.5 * live);
+        ema.restore();
+        assert_eq!(w.value().to_bits(), live.to_bits());
+
+        // train on y = 2x - 1 and evaluate with the averaged weights
+        let model = MLP::new_seeded(1, vec![4, 1], 3);
+        let xs: Vec<f64> = (0..8).map(|i| i as f64 / 4.0 - 1.0).collect();
+        let ys: Vec<f64> = xs.iter().map(|x| 2.0 * x - 1.0).collect();
+        let predict = || -> Vec<Value> {
+            xs.iter()
+                .map(|&x| model.call(&[Value::new(x)])[0].clone())
+                .collect()
+        };
+        let mut opt = SGD::new(model.parameters(), 0.05);
+        let mut ema = Ema::new(model.parameters(), 0.9);
+        for _ in 0..300 {
+            opt.zero_grad();
+            losses::mse(&predict(), &ys).backward();
+            opt.step();
+            ema.update();
+        }
+        let trained = model.parameters_snapshot();
+        ema.apply();
+        let ema_loss = losses::mse(&predict(), &ys).value();
+        assert!(
+            ema_loss.is_finite() && ema_loss < 0.1,
+            "ema loss {}",
+            ema_loss
+        );
+        ema.restore();
+        assert_eq!(model.parameters_snapshot(), trained);
+    }
+
+    #[test]
+    fn test_per_sample_losses() {
+        let preds: Vec<Value> = [0.5, -1.0, 2.0].map(Value::new).to_vec();
+        let targets = [0.0, -1.0, 5.0];
+        let terms = losses::mse_with(&preds, &targets, losses::Reduction::None).elements();
+        let (total, values) = losses::per_sample(terms, losses::Reduction::Mean);
+
+        for ((p, &t), v) in preds.iter().zip(&targets).zip(&values) {
+            assert_eq!(*v, losses::mse(std::slice::from_ref(p), &[t]).value());
+        }
+        assert_eq!(values.iter().cloned().fold(f64::MIN, f64::max), 9.0);
+        assert!((total.value() - values.iter().sum::<f64>() / 3.0).abs() < 1e-12);
+
+        total.backward();
+        // d/dp of (p - t)^2 / 3
+        assert!((preds[2].0.borrow().grad - 2.0 * (2.0 - 5.0) / 3.0).abs() < 1e-12);
+        assert_eq!(preds[1].0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_backward_from_seed() {
+        let grads = |seed: f64| {
+            let x = Value::new(1.5);
+            let y = Value::new(-0.5);
+            let f = (&(&x * &y) + &x.exp()).tanh();
+            f.backward_from(seed);
+            let gx = x.0.borrow().grad;
+            let gy = y.0.borrow().grad;
+            (gx, gy)
+        };
+        let (gx, gy) = grads(1.0);
+        let (gx2, gy2) = grads(2.0);
+        assert_eq!(gx2, 2.0 * gx);
+        assert_eq!(gy2, 2.0 * gy);
+    }
+
+    #[test]
+    fn test_named_parameters() {
+        let model = MLP::new(2, vec![3, 1]);
+        let named = model.named_parameters();
+        assert_eq!(named.len(), model.parameters().len());
+        assert_eq!(named[0].0, "layer0.neuron0.w0");
+        assert_eq!(named[2].0, "layer0.neuron0.b");
+        assert_eq!(named[5].0, "layer0.neuron1.b");
+        assert_eq!(named[9].0, "layer1.neuron0.w0");
+        assert_eq!(named[12].0, "layer1.neuron0.b");
+        for ((_, p), q) in named.iter().zip(model.parameters()) {
+            assert!(std::rc::Rc::ptr_eq(&p.0, &q.0));
+        }
+
+        let seq = MLP::builder()
+            .input(2)
+            .dense(2, Activation::Relu)
+            .prelu(0.1)
+            .build()
+            .unwrap();
+        let names: Vec<String> = seq.named_parameters().into_iter().map(|(n, _)| n).collect();
+        assert_eq!(names.last().unwrap(), "1.a0");
+        assert_eq!(names[0], "0.neuron0.w0");
+    }
+
+    #[test]
+    fn test_per_group_learning_rates() {
+        let model = MLP::new(2, vec![3, 1]);
+        let (first, rest): (Vec<_>, Vec<_>) = model
+            .named_parameters()
+            .into_iter()
+            .partition(|(name, _)| name.starts_with("layer0."));
+        let strip = |named: Vec<(String, Value)>| named.into_iter().map(|(_, p)| p).collect();
+        let mut optimizer = SGD::with_param_groups(vec![(strip(first), 0.01), (strip(rest), 0.5)]);
+
+        let out = &model.call(&[Value::new(1.0), Value::new(-2.0)])[0];
+        model.zero_grad();
+        out.backward();
+
+        let before = model.parameters_snapshot();
+        optimizer.step();
+        let diff = param_diff(&before, &model.parameters_snapshot());
+        for (i, (d, p)) in diff.iter().zip(model.parameters()).enumerate() {
+            let lr = if i < 9 { 0.01 } else { 0.5 };
+            assert!((d + lr * p.0.borrow().grad).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_sgd_from_groups() {
+        let model = MLP::new_seeded(2, vec![3, 1], 9);
+        let mut sgd = SGD::from_groups(vec![
+            ParamGroup::new(model.layer(0).unwrap().parameters(), 0.0),
+            ParamGroup::new(model.layer(1).unwrap().parameters(), 0.1),
+        ]);
+        let frozen = model.layer(0).unwrap().state();
+        let head = model.layer(1).unwrap().state();
+        for _ in 0..10 {
+            model.zero_grad();
+            let out = &model.call(&[Value::new(0.5), Value::new(-1.0)])[0];
+            (out * out).backward();
+            sgd.step();
+        }
+        assert_eq!(model.layer(0).unwrap().state(), frozen);
+        assert_ne!(model.layer(1).unwrap().state(), head);
+
+        // zero grads: only the decaying group shrinks
+        let (a, b) = (Value::new(1.0), Value::new(1.0));
+        let mut sgd = SGD::from_groups(vec![
+            ParamGroup::new(vec![a.clone()], 0.1).weight_decay(0.5),
+            ParamGroup::new(vec![b.clone()], 0.1),
+        ]);
+        sgd.step();
+        assert!((a.value() - 0.95).abs() < 1e-12);
+        assert_eq!(b.value(), 1.0);
+    }
+
+    #[test]
+    fn test_set_lr_scales_param_groups() {
+        let groups = || {
+            vec![
+                ParamGroup::new(vec![Value::new(1.0)], 0.0),
+                ParamGroup::new(vec![Value::new(1.0)], 0.2),
+                ParamGroup::new(vec![Value::new(1.0)], 0.05),
+            ]
+        };
+        let mut sgd = SGD::from_groups(groups());
+        assert_eq!(sgd.lr(), 0.2);
+        sgd.set_lr(0.1);
+        let lrs: Vec<f64> = sgd.groups().iter().map(|g| g.lr).collect();
+        assert_eq!(lrs, vec![0.0, 0.1, 0.025]);
+        // a schedule passing through zero keeps the ratios
+        sgd.set_lr(0.0);
+        sgd.set_lr(0.4);
+        let lrs: Vec<f64> = sgd.groups().iter().map(|g| g.lr).collect();
+        assert_eq!(lrs, vec![0.0, 0.4, 0.1]);
+        // unfreezing a group mid-schedule: it follows set_lr from its new rate
+        sgd.set_group_lr(0, 0.2);
+        sgd.set_lr(0.2);
+        let lrs: Vec<f64> = sgd.groups().iter().map(|g| g.lr).collect();
+        assert_eq!(lrs, vec![0.1, 0.2, 0.05]);
+
+        let mut adam = Adam::from_groups(groups());
+        adam.set_lr(0.1);
+        assert_eq!(adam.groups()[2].lr, 0.025);
+        for group in adam.groups() {
+            group.params[0].0.borrow_mut().grad = 1.0;
+        }
+        adam.step();
+        // the first Adam step moves each parameter by about its group's lr
+        for group in adam.groups() {
+            assert!((1.0 - group.params[0].value() - group.lr).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_neuron_call_sequence_accumulates_shared_grads() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let mut rng = StdRng::seed_from_u64(7);
+        let neuron = Neuron::new_seeded(2, true, &mut rng);
+        let sequence: Vec<Vec<Value>> = [[0.5, -1.0], [0.2, 0.3], [-0.7, 0.9]]
+            .iter()
+            .map(|x| x.map(Value::new).to_vec())
+            .collect();
+
+        // gradient of each step's output on its own
+        let per_step: Vec<Vec<f64>> = sequence
+            .iter()
+            .map(|x| {
+                neuron.zero_grad();
+                neuron.call(x).backward();
+                neuron
+                    .parameters()
+                    .iter()
+                    .map(|p| p.0.borrow().grad)
+                    .collect()
+            })
+            .collect();
+
+        neuron.zero_grad();
+        let outputs = neuron.call_sequence(&sequence);
+        assert_eq!(outputs.len(), 3);
+        sum_kahan(&outputs).backward();
+        for (i, p) in neuron.parameters().iter().enumerate() {
+            let expected: f64 = per_step.iter().map(|g| g[i]).sum();
+            assert!((p.0.borrow().grad - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_rnn_cell_bptt_grad_check() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let cell = RNNCell::new_seeded(2, 3, &mut StdRng::seed_from_u64(11));
+        assert_eq!(cell.parameters().len(), 3 * 2 + 3 * 3 + 3);
+        assert_eq!(cell.named_parameters()[6].0, "wh.0.0");
+
+        let sequence: Vec<Vec<Value>> = [[0.5, -0.2], [0.1, 0.8], [-0.6, 0.3]]
+            .iter()
+            .map(|x| x.map(Value::new).to_vec())
+            .collect();
+        let states = cell.unroll(&sequence);
+        assert_eq!(states.len(), 3);
+        let loss = sum_kahan(&states[2]);
+        loss.backward();
+
+        // the recurrent weights only matter from the second step on, so a
+        // correct gradient here needs backprop through time
+        let wh: Vec<Value> = cell.parameters()[6..15].to_vec();
+        let expected = complex_step_grads(&loss, &wh);
+        for (w, e) in wh.iter().zip(&expected) {
+            assert!(e.abs() > 1e-6);
+            assert!((w.0.borrow().grad - e).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_tied_transpose() {
+        let encoder = Layer::with_activation(3, 2, Activation::Tanh);
+        let decoder = Layer::tied_transpose(&encoder, Activation::Linear);
+        assert_eq!(decoder.neurons().len(), 3);
+        for (i, dec) in decoder.neurons().iter().enumerate() {
+            for (j, w) in dec.weights().iter().enumerate() {
+                assert!(w.same_node(&encoder.neurons()[j].weights()[i]));
+            }
+        }
+
+        let x = [Value::new(0.5), Value::new(-1.0), Value::new(2.0)];
+        let hidden = encoder.call(&x);
+        let out = decoder.call(&hidden);
+        let loss = out.iter().fold(Value::new(0.0), |acc, o| &acc + o);
+        loss.backward();
+
+        // Decoder-only contribution to w[0][0]: d(loss)/d(dec w00) = hidden[0]
+        let shared = &encoder.neurons()[0].weights()[0];
+        let decoder_part = hidden[0].value();
+        let encoder_part = shared.0.borrow().grad - decoder_part;
+        let dh0: f64 = (1.0 - hidden[0].value().powi(2))
+            * encoder.neurons()[0]
+                .weights()
+                .iter()
+                .map(|w| w.value())
+                .sum::<f64>();
+        assert!((encoder_part - dh0 * x[0].value()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_backward_checked() {
+        let x = Value::new(2.0);
+        let y = &x * &x;
+        assert_eq!(y.backward_checked(), Ok(()));
+
+        let x = Value::new(-1.0);
+        let w = Value::new(3.0);
+        let out = &x.log() * &w;
+        match out.backward_checked() {
+            Err(GradError::NonFiniteData { op, data, .. }) => {
+                assert_eq!(op.as_deref(), Some("Log"));
+                assert!(data.is_nan());
+            }
+            other => panic!("expected NonFiniteData, got {:?}", other),
+        }
+
+        // finite forward pass, but the gradient of sqrt at 0 is infinite
+        let z = Value::new(0.0);
+        let out = &z.exp() + &(&z * 1.0).pow(0.5);
+        assert!(matches!(
+            out.backward_checked(),
+            Err(GradError::NonFiniteGrad { .. })
+        ));
+    }
+
+    #[test]
+    fn test_ema_tracks_parameter() {
+        let p = Value::new(0.0);
+        let params = [p.clone()];
+        let mut ema = EMA::new(&params);
+
+        let decay = 0.9;
+        let mut expected = 0.0;
+        for step in 1..=20 {
+            p.0.borrow_mut().data = step as f64;
+            ema.update(&params, decay);
+            expected = decay * expected + (1.0 - decay) * step as f64;
+        }
+        assert!((ema.shadow[0] - expected).abs() < 1e-12);
+        // the average lags behind the moving parameter
+        assert!(ema.shadow[0] < 20.0);
+
+        ema.copy_to(&params);
+        assert_eq!(p.value(), expected);
+    }
+
+    #[test]
+    fn test_input_grads() {
+        let mlp = MLP::new(3, vec![4, 1]);
+        let raw = [0.5, -0.2, 0.9];
+        let loss_at = |xs: &[Value]| {
+            let diff = &mlp.call(xs)[0] + -1.0;
+            &diff * &diff
+        };
+
+        let xs: Vec<Value> = raw.iter().map(|&x| Value::new(x)).collect();
+        loss_at(&xs).backward();
+        let grads = input_grads(&xs);
+        assert!(grads.iter().any(|&g| g != 0.0));
+
+        let eps = 1e-6;
+        for (i, grad) in grads.iter().enumerate() {
+            let shifted = |d: f64| {
+                let xs: Vec<Value> = raw
+                    .iter()
+                    .enumerate()
+                    .map(|(j, &x)| Value::new(if i == j { x + d } else { x }))
+                    .collect();
+                loss_at(&xs).value()
+            };
+            let numerical = (shifted(eps) - shifted(-eps)) / (2.0 * eps);
+            assert!((numerical - grad).abs() < 1e-6);
+        }
+
+        // a small step along the gradient sign increases the loss
+        let adversarial = perturb_inputs(&xs, 1e-3);
+        assert!(loss_at(&adversarial).value() > loss_at(&xs).value());
+    }
+
+    #[test]
+    fn test_architecture_dot() {
+        let mlp = MLP::new_with_output(3, vec![4, 4, 2], Activation::Relu, Activation::Softmax);
+        let dot = mlp.architecture_dot();
+        assert!(dot.starts_with("digraph mlp {"));
+        assert_eq!(dot.matches("[label=\"layer").count(), 3);
+        assert_eq!(dot.matches(" -> layer").count(), 3);
+        assert!(dot.contains(r#"layer0 [label="layer0\n3 -> 4\nRelu"]"#));
+        assert!(dot.contains(r#"layer2 [label="layer2\n4 -> 2\nSoftmax"]"#));
+
+        // a zero-width layer has no neuron to read its input size from
+        let dot = MLP::new(2, vec![0, 1]).architecture_dot();
+        assert!(dot.contains(r#"layer0 [label="layer0\n0 -> 0\nTanh"]"#));
+        assert!(dot.contains(r#"layer1 [label="layer1\n0 -> 1\nLinear"]"#));
+    }
+
+    #[test]
+    fn test_seeded_mlp() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let values =
+            |m: &dyn Module| -> Vec<f64> { m.parameters().iter().map(|p| p.value()).collect() };
+        let a = MLP::new_seeded(3, vec![4, 2], 123);
+        assert_eq!(values(&a), values(&MLP::new_seeded(3, vec![4, 2], 123)));
+        assert_ne!(values(&a), values(&MLP::new_seeded(3, vec![4, 2], 124)));
+
+        let mut r1 = StdRng::seed_from_u64(9);
+        let mut r2 = StdRng::seed_from_u64(9);
+        let n1 = Neuron::new_seeded(5, true, &mut r1);
+        let n2 = Neuron::new_seeded(5, true, &mut r2);
+        assert_eq!(values(&n1), values(&n2));
+        let l1 = Layer::new_seeded(5, 3, false, &mut r1);
+        let l2 = Layer::new_seeded(5, 3, false, &mut r2);
+        assert_eq!(values(&l1), values(&l2));
+    }
+
+    #[test]
+    fn test_relink_after_loading_weights() {
+        let x = Value::new(0.7);
+        let w = Value::new(0.5);
+        let b = Value::new(-0.2);
+        let out = &(&(&w * &x) + &b).tanh() * &w.sigmoid();
+        out.backward();
+
+        // load new weights into the existing graph
+        w.set_data(-1.3);
+        b.set_data(0.4);
+        out.relink();
+        for v in [&x, &w, &b] {
+            v.0.borrow_mut().grad = 0.0;
+        }
+        out.backward();
+
+        let (w2, b2) = (Value::new(-1.3), Value::new(0.4));
+        let expected = &(&(&w2 * &x) + &b2).tanh() * &w2.sigmoid();
+        expected.backward();
+        assert_eq!(out.value(), expected.value());
+        assert!((w.0.borrow().grad - w2.0.borrow().grad).abs() < 1e-12);
+        assert!((b.0.borrow().grad - b2.0.borrow().grad).abs() < 1e-12);
+
+        // `Ops` and `Data` are public, so a hand-built `Sub` node must relink too
+        let (a, c) = (Value::new(3.0), Value::new(1.0));
+        let diff = Value(std::rc::Rc::new(std::cell::RefCell::new(Data {
+            data: 2.0,
+            grad: 0.0,
+            parents: vec![a.clone(), c.clone()],
+            op: Some(Ops::Sub),
+            requires_grad: true,
+            _backward: None,
+        })));
+        c.set_data(5.0);
+        diff.relink();
+        diff.backward();
+        assert_eq!(diff.value(), -2.0);
+        assert_eq!((a.0.borrow().grad, c.0.borrow().grad), (1.0, -1.0));
+    }
+
+    #[test]
+    fn test_sum_kahan() {
+        // 1e16 + 10_000 * 1.0: each 1.0 alone is below half an ulp of 1e16
+        let mut xs = vec![Value::new(1e16)];
+        xs.extend((0..10_000).map(|_| Value::new(1.0)));
+        let naive = xs.iter().fold(0.0, |acc, x| acc + x.value());
+        let kahan = sum_kahan(&xs);
+        assert_eq!(naive, 1e16);
+        assert_eq!(kahan.value(), 1e16 + 10_000.0);
+
+        kahan.backward();
+        assert!(xs.iter().all(|x| x.0.borrow().grad == 1.0));
+        assert_eq!(sum_kahan(&[]).value(), 0.0);
+    }
+
+    #[test]
+    fn test_reductions_grad_check() {
+        let eps = 1e-6;
+        type Reduce = fn(&[Value]) -> Value;
+        let reductions: [(&str, Reduce); 4] = [
+            ("sum", reduce_sum),
+            ("prod", reduce_prod),
+            ("max", reduce_max),
+            ("min", reduce_min),
+        ];
+        for raw in [[0.7, -1.3, 2.1, 0.4], [1.5, 0.0, -2.0, 3.0]] {
+            for (name, reduce) in reductions {
+                let xs: Vec<Value> = raw.iter().map(|&x| Value::new(x)).collect();
+                reduce(&xs).backward();
+                for i in 0..raw.len() {
+                    let shifted = |d: f64| {
+                        let mut r = raw;
+                        r[i] += d;
+                        reduce(&r.map(Value::new)).value()
+                    };
+                    let numerical = (shifted(eps) - shifted(-eps)) / (2.0 * eps);
+                    let grad = xs[i].0.borrow().grad;
+                    assert!(
+                        (numerical - grad).abs() < 1e-6,
+                        "{} x{}: {} vs {}",
+                        name,
+                        i,
+                        numerical,
+                        grad
+                    );
+                }
+            }
+        }
+
+        // with two zeros every partial product is zero, not NaN
+        let xs = [Value::new(0.0), Value::new(2.0), Value::new(0.0)];
+        reduce_prod(&xs).backward();
+        assert!(xs.iter().all(|x| x.0.borrow().grad == 0.0));
+        assert_eq!(reduce_prod(&[]).value(), 1.0);
+    }
+
+    #[test]
+    fn test_tape_matches_value_graph() {
+        let mut tape = Tape::new();
+        let (a, b) = (tape.leaf(0.8), tape.leaf(-1.7));
+        let ab = tape.mul(a, b);
+        let t = tape.tanh(ab);
+        let e = tape.exp(a);
+        let l = tape.log(e);
+        let s = tape.sigmoid(b);
+        let p = tape.pow(s, 3.0);
+        let r = tape.relu(a);
+        let out = tape.sum(&[t, l, p, r]);
+        tape.backward(out);
+
+        let (va, vb) = (Value::new(0.8), Value::new(-1.7));
+        let parts = [
+            (&va * &vb).tanh(),
+            va.exp().log(),
+            vb.sigmoid().pow(3.0),
+            va.relu(),
+        ];
+        let expected = reduce_sum(&parts);
+        expected.backward();
+        assert!((tape.value(out) - expected.value()).abs() < 1e-12);
+        assert!((tape.grad(a) - va.0.borrow().grad).abs() < 1e-12);
+        assert!((tape.grad(b) - vb.0.borrow().grad).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_tape_matches_rc_graph() {
+        // timing lives in benches/tape_backward.rs
+        let n = 1_000;
+        let inputs: Vec<f64> = (0..n).map(|i| (i as f64 * 0.37).sin()).collect();
+
+        let mut tape = Tape::with_capacity(4 * n);
+        let w = tape.leaf(0.5);
+        let terms: Vec<Var> = inputs
+            .iter()
+            .map(|&x| {
+                let x = tape.leaf(x);
+                let wx = tape.mul(w, x);
+                tape.tanh(wx)
+            })
+            .collect();
+        let root = tape.sum(&terms);
+        tape.backward(root);
+
+        let vw = Value::new(0.5);
+        let terms: Vec<Value> = inputs
+            .iter()
+            .map(|&x| (&vw * &Value::new(x)).tanh())
+            .collect();
+        sum_kahan(&terms).backward();
+
+        let (g_tape, g_rc) = (tape.grad(w), vw.0.borrow().grad);
+        assert!((g_tape - g_rc).abs() < 1e-9 * g_rc.abs().max(1.0));
+    }
+
+    #[test]
+    fn test_fused_linear_matches_unfused() {
+        let leaves = || {
+            let xs: Vec<Value> = [0.3, -1.2, 2.5].iter().map(|&v| Value::new(v)).collect();
+            let ws: Vec<Value> = [0.7, 0.1, -0.4].iter().map(|&v| Value::new(v)).collect();
+            (xs, ws, Value::new(0.25))
+        };
+        let grads = |vs: &[Value]| vs.iter().map(|v| v.0.borrow().grad).collect::<Vec<_>>();
+
+        let (xs, ws, b) = leaves();
+        let fused = Value::linear(&xs, &ws, &b).tanh();
+        fused.backward();
+
+        let (ux, uw, ub) = leaves();
+        let unfused = uw
+            .iter()
+            .zip(&ux)
+            .fold(ub.clone(), |acc, (w, x)| &acc + &(w * x))
+            .tanh();
+        unfused.backward();
+
+        assert_eq!(fused.value(), unfused.value());
+        assert_eq!(grads(&xs), grads(&ux));
+        assert_eq!(grads(&ws), grads(&uw));
+        assert_eq!(b.0.borrow().grad, ub.0.borrow().grad);
+        // 7 leaves + linear + tanh, versus 7 leaves + 3 muls + 3 adds + tanh
+        assert_eq!(fused.to_adjacency().0.len(), 9);
+        assert_eq!(unfused.to_adjacency().0.len(), 14);
+    }
+
+    #[test]
+    fn test_module_state_round_trip() {
+        let model = MLP::new_seeded(2, vec![4, 4, 1], 7);
+        let inputs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let targets = [0.0, 1.0, 1.0, 0.0];
+        let outputs = |m: &MLP| -> Vec<f64> {
+            inputs
+                .iter()
+                .map(|x| m.call(&[Value::new(x[0]), Value::new(x[1])])[0].value())
+                .collect()
+        };
+
+        let saved = model.state();
+        let before = outputs(&model);
+        for p in model.parameters() {
+            p.0.borrow_mut().data += 0.3;
+        }
+        assert_ne!(outputs(&model), before);
+        model.load_state(&saved).unwrap();
+        assert_eq!(outputs(&model), before);
+
+        assert_eq!(
+            model.load_state(&saved[1..]),
+            Err(StateError::LengthMismatch {
+                expected: saved.len(),
+                got: saved.len() - 1
+            })
+        );
+
+        let mut optimizer = SGD::new(model.parameters(), 0.2);
+        for _ in 0..300 {
+            let preds: Vec<Value> = inputs
+                .iter()
+                .map(|x| model.call(&[Value::new(x[0]), Value::new(x[1])])[0].clone())
+                .collect();
+            let loss = losses::mse(&preds, &targets);
+            model.zero_grad();
+            loss.backward();
+            optimizer.step();
+        }
+        let trained = model.state();
+        let fresh = MLP::new_seeded(2, vec![4, 4, 1], 99);
+        fresh.load_state(&trained).unwrap();
+        for (pred, target) in outputs(&fresh).iter().zip(targets) {
+            assert!((pred - target).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn test_dropping_graphs() {
+        let x = Value::new(0.5);
+        let y = (&x * &Value::new(2.0)).tanh();
+        let weak = std::rc::Rc::downgrade(&y.0);
+        let out = &y + &x;
+        assert!(out.graph_memory_estimate() > y.graph_memory_estimate());
+        out.backward();
+        drop(y);
+        drop(out);
+        // nothing but the dropped root referenced the intermediate node
+        assert!(weak.upgrade().is_none());
+
+        let mut chain = Value::new(0.1);
+        let first = std::rc::Rc::downgrade(&chain.0);
+        for _ in 0..1_000_000 {
+            chain = chain.relu();
+        }
+        drop(chain);
+        assert!(first.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_jvp() {
+        let f = |x: &[Value]| &(&x[0] * &x[1]).tanh() + &x[2].exp();
+        let raw = [0.4, -0.9, 0.2];
+        let direction = [1.0, -2.0, 0.5];
+
+        let xs: Vec<Value> = raw.iter().map(|&v| Value::new(v)).collect();
+        let out = f(&xs);
+        // stale grads from an earlier pass must not leak into the result
+        out.backward();
+        let jvp = jvp(&xs, &out, &direction);
+        let expected: f64 = input_grads(&xs)
+            .iter()
+            .zip(&direction)
+            .map(|(g, d)| g * d)
+            .sum();
+        assert!((jvp - expected).abs() < 1e-12);
+
+        let eps = 1e-6;
+        let at = |t: f64| {
+            let shifted: Vec<Value> = raw
+                .iter()
+                .zip(&direction)
+                .map(|(x, d)| Value::new(x + t * d))
+                .collect();
+            f(&shifted).value()
+        };
+        assert!((jvp - (at(eps) - at(-eps)) / (2.0 * eps)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_sgd_zero_momentum_matches_plain_step() {
+        let run = |momentum: f64| {
+            let w = Value::new(1.5);
+            let mut sgd = SGD::builder()
+                .lr(0.1)
+                .momentum(momentum)
+                .build(vec![w.clone()]);
+            let mut trace = vec![];
+            for _ in 0..10 {
+                w.0.borrow_mut().grad = 0.0;
+                (&w * &w).backward();
+                sgd.step();
+                trace.push(w.value());
+            }
+            trace
+        };
+        let mut expected = vec![];
+        let mut x: f64 = 1.5;
+        for _ in 0..10 {
+            x -= 0.1 * 2.0 * x;
+            expected.push(x);
+        }
+        assert_eq!(run(0.0), expected);
+    }
+
+    #[test]
+    fn test_sgd_momentum_converges_faster() {
+        // f(w) = 0.5 * 0.01 * w^2: a shallow bowl where plain SGD crawls
+        let steps_to_min = |momentum: f64| {
+            let w = Value::new(10.0);
+            let mut sgd = SGD::builder()
+                .lr(0.5)
+                .momentum(momentum)
+                .build(vec![w.clone()]);
+            for step in 1..=10_000 {
+                w.0.borrow_mut().grad = 0.0;
+                (&(&w * &w) * 0.005).backward();
+                sgd.step();
+                if w.value().abs() < 1e-3 {
+                    return step;
+                }
+            }
+            usize::MAX
+        };
+        assert!(steps_to_min(0.9) < steps_to_min(0.0));
+    }
+
+    #[test]
+    fn test_sgd_nesterov_two_step_trace() {
+        // Loss = w * c has gradient c regardless of w, so the trace is exact:
+        //   step 1 (g = 2): v = 2,             w = 1 - 0.1 * (2 + 0.5 * 2)   = 0.7
+        //   step 2 (g = 4): v = 0.5 * 2 + 4 = 5, w = 0.7 - 0.1 * (4 + 0.5 * 5) = 0.05
+        let w = Value::new(1.0);
+        let mut sgd = SGD::builder()
+            .lr(0.1)
+            .momentum(0.5)
+            .nesterov(true)
+            .build(vec![w.clone()]);
+        let mut trace = vec![];
+        for c in [2.0, 4.0] {
+            w.0.borrow_mut().grad = 0.0;
+            (&w * c).backward();
+            sgd.step();
+            trace.push(w.value());
+        }
+        assert!((trace[0] - 0.7).abs() < 1e-12);
+        assert!((trace[1] - 0.05).abs() < 1e-12);
+        assert_eq!(sgd.velocity, vec![5.0]);
+
+        // Classical momentum on the same gradients: w = 1 - 0.2 = 0.8, then 0.8 - 0.5 = 0.3
+        let w = Value::new(1.0);
+        let mut sgd = SGD::builder().lr(0.1).momentum(0.5).build(vec![w.clone()]);
+        for c in [2.0, 4.0] {
+            w.0.borrow_mut().grad = 0.0;
+            (&w * c).backward();
+            sgd.step();
+        }
+        assert!((w.value() - 0.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_sgd_weight_decay() {
+        // Zero gradients: each step multiplies w by 1 - lr * wd = 0.99
+        let w = Value::new(2.0);
+        let frozen = Value::new(2.0);
+        frozen.0.borrow_mut().requires_grad = false;
+        let bias = Value::new(2.0);
+        let mut sgd = SGD::builder()
+            .lr(0.1)
+            .weight_decay(0.1)
+            .no_decay(std::slice::from_ref(&bias))
+            .build(vec![w.clone(), frozen.clone(), bias.clone()]);
+        let mut expected = 2.0;
+        for _ in 0..5 {
+            sgd.step();
+            expected *= 0.99;
+            assert!((w.value() - expected).abs() < 1e-12);
+        }
+        assert_eq!(frozen.value(), 2.0);
+        assert_eq!(bias.value(), 2.0);
+
+        // wd = 0 is bit-for-bit the plain update
+        let w = Value::new(0.75);
+        let mut sgd = SGD::builder()
+            .lr(0.1)
+            .weight_decay(0.0)
+            .build(vec![w.clone()]);
+        let mut x: f64 = 0.75;
+        for _ in 0..10 {
+            w.0.borrow_mut().grad = 0.0;
+            (&w * &w).backward();
+            sgd.step();
+            x -= 0.1 * (2.0 * x);
+            assert_eq!(w.value(), x);
+        }
+    }
+
+    #[test]
+    fn test_xor_converges_with_weight_decay() {
+        let xs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let ys = [-1.0, 1.0, 1.0, -1.0];
+        let model = MLP::new_seeded(2, vec![4, 1], 7);
+        let biases: Vec<Value> = model
+            .named_parameters()
+            .into_iter()
+            .filter(|(name, _)| name.ends_with(".b"))
+            .map(|(_, p)| p)
+            .collect();
+        assert_eq!(biases.len(), 5);
+        let mut sgd = SGD::builder()
+            .lr(0.1)
+            .weight_decay(1e-4)
+            .no_decay(&biases)
+            .build(model.parameters());
+        let mut loss = f64::INFINITY;
+        for _ in 0..500 {
+            model.zero_grad();
+            let preds: Vec<Value> = xs
+                .iter()
+                .map(|x| model.call(&x.map(Value::new))[0].clone())
+                .collect();
+            let l = losses::mse(&preds, &ys);
+            l.backward();
+            sgd.step();
+            loss = l.value();
+        }
+        assert!(loss < 0.05, "loss = {}", loss);
+    }
+
+    #[test]
+    fn test_sgd_velocity_len_matches_params() {
+        let mlp = MLP::new_seeded(3, vec![4, 2], 0);
+        let params = mlp.parameters();
+        let mut sgd = SGD::builder().momentum(0.9).build(params.clone());
+        // allocated on the first step, and never without momentum
+        assert!(sgd.velocity.is_empty());
+        sgd.step();
+        assert_eq!(sgd.velocity.len(), params.len());
+        let mut plain = SGD::new(params.clone(), 0.1);
+        plain.step();
+        assert!(plain.velocity.is_empty());
+        assert_eq!(plain.state().buffers[0].1, vec![0.0; params.len()]);
+    }
+
+    #[test]
+    fn test_value_pool_recycles_nodes() {
+        let xs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let ys = [0.0, 1.0, 1.0, 0.0];
+        let epoch = |model: &MLP| {
+            let preds: Vec<Value> = xs
+                .iter()
+                .map(|x| model.call(&x.map(Value::new))[0].clone())
+                .collect();
+            losses::mse(&preds, &ys)
+        };
+        let train = |pool: Option<&mut ValuePool>| {
+            let model = MLP::new_seeded(2, vec![4, 1], 3);
+            let mut sgd = SGD::new(model.parameters(), 0.1);
+            let mut pool = pool;
+            for _ in 0..1000 {
+                model.zero_grad();
+                let loss = match pool.as_deref_mut() {
+                    Some(pool) => pool.scope(|| epoch(&model)),
+                    None => epoch(&model),
+                };
+                loss.backward();
+                sgd.step();
+            }
+            model.state()
+        };
+
+        let mut pool = ValuePool::new();
+        assert_eq!(train(Some(&mut pool)), train(None));
+
+        // Without the pool every epoch allocates a whole graph; with it only
+        // the first one does and the other 999 reuse its nodes.
+        let per_epoch = pool.allocations();
+        assert!(per_epoch > 0);
+        assert_eq!(pool.reuses(), 999 * per_epoch);
+    }
+
+    #[test]
+    fn test_no_grad() {
+        let mlp = MLP::new_seeded(2, vec![3, 1], 5);
+        let x = [Value::new(0.5), Value::new(-1.0)];
+        let expected = mlp.call(&x)[0].value();
+
+        let out = no_grad(|| mlp.call(&x)[0].clone());
+        assert!(is_grad_enabled());
+        assert_eq!(out.value(), expected);
+        assert!(out.0.borrow().parents.is_empty());
+
+        out.backward();
+        for p in mlp.parameters().iter().chain(&x) {
+            assert_eq!(p.0.borrow().grad, 0.0);
+        }
+    }
+
+    #[test]
+    fn test_average_models() {
+        let a = MLP::new_seeded(2, vec![3, 1], 1);
+        let b = MLP::new_seeded(2, vec![3, 1], 2);
+        let avg = average_models(&[&a, &b]).unwrap();
+        for ((m, x), y) in avg.iter().zip(a.state()).zip(b.state()) {
+            assert!((m - (x + y) / 2.0).abs() < 1e-15);
+        }
+
+        let merged = MLP::new_seeded(2, vec![3, 1], 3);
+        merged.load_state(&avg).unwrap();
+        assert_eq!(merged.state(), avg);
+
+        let other = MLP::new_seeded(2, vec![4, 1], 1);
+        assert_eq!(
+            average_models(&[&a, &other]),
+            Err(StateError::LengthMismatch {
+                expected: 13,
+                got: 17
+            })
+        );
+    }
+
+    #[test]
+    fn test_is_leaf_and_parents() {
+        let a = Value::new(1.0);
+        let b = Value::new(2.0);
+        assert!(a.is_leaf());
+        assert!(a.parents().is_empty());
+
+        let c = &a + &b;
+        assert!(!c.is_leaf());
+        let parents = c.parents();
+        assert_eq!(parents.len(), 2);
+        assert!(parents[0].same_node(&a) && parents[1].same_node(&b));
+    }
+
+    #[test]
+    fn test_per_layer_grad_norm() {
+        let mlp = MLP::new_seeded(3, vec![4, 4, 1], 2);
+        let x = [0.5, -1.0, 0.25].map(Value::new);
+        let out = mlp.call(&x)[0].clone();
+        (&out * &out).backward();
+
+        let norms = mlp.per_layer_grad_norm();
+        assert_eq!(norms.len(), 3);
+        assert!(norms.iter().all(|n| n.is_finite() && *n > 0.0));
+        let total = norms.iter().map(|n| n * n).sum::<f64>().sqrt();
+        assert!((total - mlp.grad_norm()).abs() < 1e-12);
+    }
+
+    #[cfg(feature = "ndarray")]
+    #[test]
+    fn test_forward_array() {
+        let mlp = MLP::new_seeded(3, vec![4, 2], 6);
+        let x = ndarray::arr1(&[0.5, -0.25, 1.0]);
+        let out = mlp.forward_array(&x);
+        let expected: Vec<f64> = mlp
+            .forward(&crate::array::to_values(&x))
+            .iter()
+            .map(|v| v.value())
+            .collect();
+        assert_eq!(out.to_vec(), expected);
+    }
+
+    #[test]
+    fn test_grad_check() {
+        let x = Value::new(1.234);
+        let y = Value::new(-2.345);
+
+        let f = (&x * &y).tanh();
+        f.backward();
+
+        let eps = 1e-6;
+        let x_plus = Value::new(1.234 + eps);
+        let x_minus = Value::new(1.234 - eps);
+
+        let f_plus = (&x_plus * &y).tanh().value();
+        let f_minus = (&x_minus * &y).tanh().value();
+
+        let numerical = (f_plus - f_minus) / (2.0 * eps);
+        let autodiff = x.0.borrow().grad;
+
+        assert!((numerical - autodiff).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_complex_step_grads() {
+        for x0 in [0.3, -1.7, 4.0] {
+            let x = Value::new(x0);
+            let f = x.tanh();
+            let cs = complex_step_grads(&f, std::slice::from_ref(&x));
+            let analytic = 1.0 - x0.tanh().powi(2);
+            assert!((cs[0] - analytic).abs() < 1e-12);
+        }
+
+        // Agrees with backward across a mix of ops, including a reused input.
+        let xs: Vec<Value> = [0.7, -0.4, 1.3].into_iter().map(Value::new).collect();
+        let f = &(&(&xs[0] * &xs[1]).sigmoid() + &xs[2].pow(3.0).log())
+            + &Value::linear(&xs, &xs, &xs[0].softplus()).exp();
+        f.backward();
+        let cs = complex_step_grads(&f, &xs);
+        for (c, g) in cs.iter().zip(input_grads(&xs)) {
+            assert!((c - g).abs() < 1e-12 * g.abs().max(1.0));
+        }
+    }
+
+    #[test]
+    fn test_hardtanh_hardsigmoid_grad_check() {
+        let eps = 1e-6;
+        let check = |f: &dyn Fn(&Value) -> Value, x0: f64, expected: f64| {
+            let x = Value::new(x0);
+            f(&x).backward();
+            assert_eq!(x.0.borrow().grad, expected, "x = {}", x0);
+            // away from the kinks the finite difference agrees
+            if (x0.abs() - 1.0).abs() > eps && (x0.abs() - 2.5).abs() > eps {
+                let numerical = (f(&Value::new(x0 + eps)).value()
+                    - f(&Value::new(x0 - eps)).value())
+                    / (2.0 * eps);
+                assert!((numerical - expected).abs() < 1e-6, "x = {}", x0);
+            }
+        };
+
+        let ht = |x: &Value| x.hardtanh(-1.0, 1.0);
+        for (x0, g) in [(0.3, 1.0), (-1.0, 0.0), (1.0, 0.0), (-3.0, 0.0), (1.5, 0.0)] {
+            check(&ht, x0, g);
+        }
+        assert_eq!(Value::new(4.0).hardtanh(-1.0, 1.0).value(), 1.0);
+
+        let hs = |x: &Value| x.hardsigmoid();
+        for (x0, g) in [(0.0, 0.2), (-2.5, 0.0), (2.5, 0.0), (-4.0, 0.0), (3.0, 0.0)] {
+            check(&hs, x0, g);
+        }
+        assert_eq!(Value::new(0.0).hardsigmoid().value(), 0.5);
+        assert_eq!(Value::new(-7.0).hardsigmoid().value(), 0.0);
+    }
+
+    #[test]
+    fn test_nan_to_num() {
+        let w = Value::new(2.0);
+        let bad = Value::new(f64::NAN);
+        let out = &w * &bad.nan_to_num(0.5);
+        assert_eq!(out.value(), 1.0);
+        out.backward();
+        assert_eq!(bad.0.borrow().grad, 0.0);
+        assert_eq!(w.0.borrow().grad, 0.5);
+        w.0.borrow_mut().grad = 0.0;
+
+        // finite values pass through with gradient 1
+        let x = Value::new(1.5);
+        let out = (&w * &x).nan_to_num(0.0);
+        out.backward();
+        assert_eq!(out.value(), 3.0);
+        assert_eq!(x.0.borrow().grad, 2.0);
+        assert_eq!(Value::new(f64::INFINITY).nan_to_num(-1.0).value(), -1.0);
+    }
+
+    #[test]
+    fn test_elu_grad_check() {
+        let eps = 1e-6;
+        for x0 in [1.5, -0.7] {
+            let x = Value::new(x0);
+            let f = x.elu(0.5);
+            f.backward();
+
+            let f_plus = Value::new(x0 + eps).elu(0.5).value();
+            let f_minus = Value::new(x0 - eps).elu(0.5).value();
+            let numerical = (f_plus - f_minus) / (2.0 * eps);
+
+            assert!((numerical - x.0.borrow().grad).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_softplus_grad_check() {
+        let eps = 1e-6;
+        for x0 in [0.8, -1.3] {
+            let x = Value::new(x0);
+            let f = x.softplus();
+            f.backward();
+
+            let f_plus = Value::new(x0 + eps).softplus().value();
+            let f_minus = Value::new(x0 - eps).softplus().value();
+            let numerical = (f_plus - f_minus) / (2.0 * eps);
+
+            assert!((numerical - x.0.borrow().grad).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_softplus_large_input() {
+        let x = Value::new(1000.0);
+        let f = x.softplus();
+        f.backward();
+        assert_eq!(f.value(), 1000.0);
+        assert_eq!(x.0.borrow().grad, 1.0);
+
+        let y = Value::new(-1000.0);
+        let g = y.softplus();
+        g.backward();
+        assert_eq!(g.value(), 0.0);
+        assert_eq!(y.0.borrow().grad, 0.0);
+    }
+
+    #[test]
+    fn test_sigmoid_head_xor_with_bce() {
+        use rand::SeedableRng;
+
+        let model = MLP::new_with_init_rng(
+            2,
+            vec![8, 8, 1],
+            Activation::Tanh,
+            Activation::Sigmoid,
+            Init::default(),
+            &mut rand::rngs::StdRng::seed_from_u64(7),
+        );
+        let mut optimizer = SGD::new(model.parameters(), 0.1);
+
+        let inputs = [
+            vec![Value::new(0.0), Value::new(0.0)],
+            vec![Value::new(0.0), Value::new(1.0)],
+            vec![Value::new(1.0), Value::new(0.0)],
+            vec![Value::new(1.0), Value::new(1.0)],
+        ];
+        let targets = [0.0, 1.0, 1.0, 0.0];
+
+        for _ in 0..300 {
+            let mut total_loss = Value::new(0.0);
+            for (x, y_true) in inputs.iter().zip(targets.iter()) {
+                let p = &model.call(x)[0];
+                // Binary cross-entropy: -(y*ln(p) + (1-y)*ln(1-p))
+                let loss = if *y_true == 1.0 {
+                    -&p.log()
+                } else {
+                    -&(1.0 + &-p).log()
+                };
+                total_loss = &total_loss + &loss;
+            }
+            model.zero_grad();
+            total_loss.backward();
+            optimizer.step();
+        }
+
+        for (x, y_true) in inputs.iter().zip(targets.iter()) {
+            let pred = model.call(x)[0].value();
+            assert!(pred > 0.0 && pred < 1.0);
+            assert_eq!(pred > 0.5, *y_true == 1.0);
+        }
+    }
+
+    #[test]
+    fn test_softmax_head_sums_to_one() {
+        let model = MLP::new_with_output(3, vec![4, 5], Activation::Relu, Activation::Softmax);
+        let x = [Value::new(0.3), Value::new(-2.0), Value::new(1.1)];
+        let out = model.call(&x);
+        assert_eq!(out.len(), 5);
+        let sum: f64 = out.iter().map(|v| v.value()).sum();
+        assert!((sum - 1.0).abs() < 1e-12);
+        assert!(out.iter().all(|v| v.value() > 0.0));
+    }
+
+    #[test]
+    fn test_mse_value_and_grad() {
+        let preds = [Value::new(1.0), Value::new(-2.0), Value::new(0.5)];
+        let targets = [0.0, 1.0, 0.5];
+        let loss = losses::mse(&preds, &targets);
+        assert!((loss.value() - 10.0 / 3.0).abs() < 1e-12);
+
+        loss.backward();
+        for (p, t) in preds.iter().zip(targets) {
+            let expected = 2.0 * (p.value() - t) / 3.0;
+            assert!((p.0.borrow().grad - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "mse: got 2 predictions but 1 targets")]
+    fn test_mse_length_mismatch() {
+        losses::mse(&[Value::new(1.0), Value::new(2.0)], &[1.0]);
+    }
+
+    #[test]
+    #[should_panic(expected = "cannot take the mean of zero samples")]
+    fn test_mean_loss_of_no_samples() {
+        losses::mse(&[], &[]);
+    }
+
+    #[test]
+    fn test_xor_with_mse_helper() {
+        let model = MLP::new(2, vec![4, 4, 1]);
+        // Mean over 4 samples, so 4x the showcase's learning rate on a summed loss
+        let mut optimizer = SGD::new(model.parameters(), 0.2);
+        let inputs = [
+            vec![Value::new(0.0), Value::new(0.0)],
+            vec![Value::new(0.0), Value::new(1.0)],
+            vec![Value::new(1.0), Value::new(0.0)],
+            vec![Value::new(1.0), Value::new(1.0)],
+        ];
+        let targets = [0.0, 1.0, 1.0, 0.0];
+
+        for _ in 0..300 {
+            let preds: Vec<Value> = inputs.iter().map(|x| model.call(x)[0].clone()).collect();
+            let loss = losses::mse(&preds, &targets);
+            model.zero_grad();
+            loss.backward();
+            optimizer.step();
+        }
+
+        for (x, y_true) in inputs.iter().zip(targets.iter()) {
+            assert!((model.call(x)[0].value() - y_true).abs() < 0.2);
+        }
+    }
+
+    #[test]
+    fn test_cross_entropy_grad() {
+        let logits = [Value::new(1.0), Value::new(-0.5), Value::new(2.0)];
+        let loss = losses::cross_entropy(&logits, 1);
+        loss.backward();
+
+        let probs = softmax(&logits);
+        for (i, (l, p)) in logits.iter().zip(&probs).enumerate() {
+            let one_hot = if i == 1 { 1.0 } else { 0.0 };
+            assert!((l.0.borrow().grad - (p.value() - one_hot)).abs() < 1e-6);
+        }
+        assert!((loss.value() + probs[1].value().ln()).abs() < 1e-9);
+
+        let huge = [Value::new(1000.0), Value::new(0.0), Value::new(-1000.0)];
+        let loss = losses::cross_entropy(&huge, 1);
+        loss.backward();
+        assert!((loss.value() - 1000.0).abs() < 1e-9);
+        assert!(huge.iter().all(|l| l.0.borrow().grad.is_finite()));
+    }
+
+    #[test]
+    #[should_panic(expected = "target class 3 out of range for 3 logits")]
+    fn test_cross_entropy_bad_target() {
+        losses::cross_entropy(&[Value::new(0.0), Value::new(0.0), Value::new(0.0)], 3);
+    }
+
+    #[test]
+    fn test_cross_entropy_trains_three_classes() {
+        let centers = [(2.0, 0.0), (-1.0, 1.7), (-1.0, -1.7)];
+        let offsets = [(0.0, 0.0), (0.3, 0.2), (-0.2, 0.3), (0.1, -0.3)];
+        let mut data = vec![];
+        for (class, (cx, cy)) in centers.iter().enumerate() {
+            for (dx, dy) in offsets {
+                data.push((vec![Value::new(cx + dx), Value::new(cy + dy)], class));
+            }
+        }
+
+        let model = MLP::new(2, vec![8, 3]);
+        let mut optimizer = SGD::new(model.parameters(), 0.1);
+        let targets: Vec<usize> = data.iter().map(|(_, c)| *c).collect();
+        for _ in 0..100 {
+            let logits: Vec<Vec<Value>> = data.iter().map(|(x, _)| model.call(x)).collect();
+            let loss = losses::cross_entropy_batch(&logits, &targets);
+            model.zero_grad();
+            loss.backward();
+            optimizer.step();
+        }
+
+        let correct = data
+            .iter()
+            .filter(|(x, class)| {
+                let out = model.call(x);
+                let best = (0..3)
+                    .max_by(|&i, &j| out[i].value().total_cmp(&out[j].value()))
+                    .unwrap();
+                best == *class
+            })
+            .count();
+        assert_eq!(correct, data.len());
+    }
+
+    #[test]
+    fn test_huber_gradients() {
+        let delta = 1.0;
+        // Errors of 0.5 (quadratic), 3.0 and -2.0 (linear)
+        let preds = [Value::new(1.5), Value::new(3.0), Value::new(-2.0)];
+        let targets = [1.0, 0.0, 0.0];
+        let loss = losses::huber(&preds, &targets, delta);
+        loss.backward();
+        let grads: Vec<f64> = preds.iter().map(|p| p.0.borrow().grad).collect();
+        assert_eq!(grads, vec![0.5 / 3.0, 1.0 / 3.0, -1.0 / 3.0]);
+
+        let eps = 1e-6;
+        for e0 in [delta - 1e-3, delta + 1e-3, -delta - 1e-3, -delta + 1e-3] {
+            let p = Value::new(e0);
+            losses::huber(std::slice::from_ref(&p), &[0.0], delta).backward();
+            let f = |x: f64| losses::huber(&[Value::new(x)], &[0.0], delta).value();
+            let numerical = (f(e0 + eps) - f(e0 - eps)) / (2.0 * eps);
+            assert!((numerical - p.0.borrow().grad).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_mae_value_and_grad() {
+        let preds = [Value::new(2.0), Value::new(-1.0), Value::new(0.5)];
+        let targets = [1.0, 1.0, 0.5];
+        let loss = losses::mae(&preds, &targets);
+        assert!((loss.value() - 1.0).abs() < 1e-12);
+
+        loss.backward();
+        let grads: Vec<f64> = preds.iter().map(|p| p.0.borrow().grad).collect();
+        // The exact hit takes Value::abs's zero subgradient
+        assert_eq!(grads, vec![1.0 / 3.0, -1.0 / 3.0, 0.0]);
+    }
+
+    #[test]
+    fn test_mae_is_robust_to_outliers() {
+        // y = 2x + 1 with one corrupted target
+        let xs: Vec<f64> = (0..10).map(|i| i as f64 * 0.5).collect();
+        let mut ys: Vec<f64> = xs.iter().map(|x| 2.0 * x + 1.0).collect();
+        ys[9] = 40.0;
+
+        let train = |loss_fn: fn(&[Value], &[f64]) -> Value| {
+            let neuron =
+                Neuron::from_weights(vec![Value::new(0.0)], Value::new(0.0), Activation::Linear);
+            let mut optimizer = SGD::new(neuron.parameters(), 0.02);
+            for _ in 0..2000 {
+                let preds: Vec<Value> = xs.iter().map(|&x| neuron.call(&[Value::new(x)])).collect();
+                let loss = loss_fn(&preds, &ys);
+                neuron.zero_grad();
+                loss.backward();
+                optimizer.step();
+            }
+            let mut errors: Vec<f64> = xs
+                .iter()
+                .zip(&ys)
+                .map(|(&x, y)| (neuron.call(&[Value::new(x)]).value() - y).abs())
+                .collect();
+            errors.sort_by(f64::total_cmp);
+            errors[errors.len() / 2]
+        };
+
+        let mae_median = train(losses::mae);
+        let mse_median = train(losses::mse);
+        assert!(mae_median < mse_median, "{} vs {}", mae_median, mse_median);
+        assert!(mae_median < 0.1);
+    }
+
+    #[test]
+    fn test_kl_div() {
+        // Identical distributions: zero divergence, zero gradient on the logits
+        let logits = [Value::new(0.2), Value::new(-1.0), Value::new(1.5)];
+        let q: Vec<Value> = softmax(&logits)
+            .iter()
+            .map(|p| Value::new(p.value()))
+            .collect();
+        let kl = losses::kl_div(&log_softmax(&logits), &q);
+        kl.backward();
+        assert!(kl.value().abs() < 1e-12);
+        assert!(logits.iter().all(|l| l.0.borrow().grad.abs() < 1e-12));
+
+        let p_log = [Value::new(0.5_f64.ln()), Value::new(0.5_f64.ln())];
+        let q = [Value::new(0.25), Value::new(0.75)];
+        let kl = losses::kl_div(&p_log, &q);
+        let expected = 0.25 * (0.25_f64 / 0.5).ln() + 0.75 * (0.75_f64 / 0.5).ln();
+        assert!((kl.value() - expected).abs() < 1e-12);
+
+        // Zero probabilities stay finite
+        let q = [Value::new(0.0), Value::new(1.0)];
+        let kl = losses::kl_div(&p_log, &q);
+        kl.backward();
+        assert!((kl.value() - 2.0_f64.ln()).abs() < 1e-12);
+        assert!(q.iter().all(|qi| qi.0.borrow().grad.is_finite()));
+    }
+
+    #[test]
+    fn test_kl_div_grad_check() {
+        let f = |l: [f64; 3], q: [f64; 3]| {
+            let logits: Vec<Value> = l.iter().map(|&x| Value::new(x)).collect();
+            let q: Vec<Value> = q.iter().map(|&x| Value::new(x)).collect();
+            losses::kl_div(&log_softmax(&logits), &q).value()
+        };
+        let l0 = [0.3, -0.7, 1.1];
+        let q0 = [0.2, 0.5, 0.3];
+        let logits: Vec<Value> = l0.iter().map(|&x| Value::new(x)).collect();
+        let q: Vec<Value> = q0.iter().map(|&x| Value::new(x)).collect();
+        losses::kl_div(&log_softmax(&logits), &q).backward();
+
+        let eps = 1e-6;
+        for i in 0..3 {
+            let (mut plus, mut minus) = (l0, l0);
+            plus[i] += eps;
+            minus[i] -= eps;
+            let numerical = (f(plus, q0) - f(minus, q0)) / (2.0 * eps);
+            assert!((numerical - logits[i].0.borrow().grad).abs() < 1e-6);
+
+            let (mut plus, mut minus) = (q0, q0);
+            plus[i] += eps;
+            minus[i] -= eps;
+            let numerical = (f(l0, plus) - f(l0, minus)) / (2.0 * eps);
+            assert!((numerical - q[i].0.borrow().grad).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_loss_reductions() {
+        let preds = || vec![Value::new(1.0), Value::new(-2.0), Value::new(0.5)];
+        let targets = [0.0, 1.0, 3.0];
+
+        let mean = losses::mse_with(&preds(), &targets, losses::Reduction::Mean).scalar();
+        let sum = losses::mse_with(&preds(), &targets, losses::Reduction::Sum).scalar();
+        assert!((sum.value() - 3.0 * mean.value()).abs() < 1e-12);
+
+        // losses::Reduction::None followed by a manual mean matches Mean's gradient
+        let (p1, p2) = (preds(), preds());
+        losses::mse_with(&p1, &targets, losses::Reduction::Mean)
+            .scalar()
+            .backward();
+        let elements = losses::mse_with(&p2, &targets, losses::Reduction::None).elements();
+        assert_eq!(elements.len(), 3);
+        let manual = elements.iter().fold(Value::new(0.0), |acc, e| &acc + e);
+        (&manual * (1.0 / 3.0)).backward();
+        for (a, b) in p1.iter().zip(&p2) {
+            assert_eq!(a.0.borrow().grad, b.0.borrow().grad);
+        }
+
+        let n = 3.0;
+        let mae = losses::mae_with(&preds(), &targets, losses::Reduction::Sum).scalar();
+        assert!((mae.value() - n * losses::mae(&preds(), &targets).value()).abs() < 1e-12);
+
+        let huber = losses::huber_with(&preds(), &targets, 1.0, losses::Reduction::None).elements();
+        assert_eq!(huber.len(), 3);
+        assert_eq!(huber[0].value(), 0.5);
+
+        let logits = vec![vec![Value::new(1.0), Value::new(0.0)]; 2];
+        let ce =
+            losses::cross_entropy_batch_with(&logits, &[0, 1], losses::Reduction::Sum).scalar();
+        let ce_mean = losses::cross_entropy_batch(&logits, &[0, 1]);
+        assert!((ce.value() - 2.0 * ce_mean.value()).abs() < 1e-12);
+
+        let p_log = log_softmax(&[Value::new(0.0), Value::new(1.0)]);
+        let q = [Value::new(0.3), Value::new(0.7)];
+        let kl_terms = losses::kl_div_with(&p_log, &q, losses::Reduction::None).elements();
+        let kl_sum: f64 = kl_terms.iter().map(|t| t.value()).sum();
+        assert!((kl_sum - losses::kl_div(&p_log, &q).value()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_cross_entropy_smoothed() {
+        let raw = [0.5, -1.0, 2.0, 0.1];
+        let logits = || raw.iter().map(|&x| Value::new(x)).collect::<Vec<_>>();
+
+        let plain = losses::cross_entropy(&logits(), 2);
+        let smoothed = losses::cross_entropy_smoothed(&logits(), 2, 0.0);
+        assert!((plain.value() - smoothed.value()).abs() < 1e-12);
+
+        let eps = 0.1;
+        let xs = logits();
+        losses::cross_entropy_smoothed(&xs, 2, eps).backward();
+        let probs: Vec<f64> = softmax(&logits()).iter().map(|p| p.value()).collect();
+        for (i, x) in xs.iter().enumerate() {
+            let target = if i == 2 {
+                1.0 - eps + eps / 4.0
+            } else {
+                eps / 4.0
+            };
+            assert!((x.0.borrow().grad - (probs[i] - target)).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "not in [0, 1)")]
+    fn test_cross_entropy_smoothed_rejects_eps() {
+        let logits = [Value::new(0.0), Value::new(1.0)];
+        losses::cross_entropy_smoothed(&logits, 0, 1.0);
+    }
+
+    #[test]
+    fn test_focal_loss() {
+        let raw = [2.0, -0.5, 0.3, -3.0];
+        let targets = [1.0, 1.0, 0.0, 0.0];
+
+        // gamma = 0, alpha = 1 is binary cross-entropy
+        let logits: Vec<Value> = raw.iter().map(|&x| Value::new(x)).collect();
+        let focal = losses::focal(&logits, &targets, 0.0, 1.0);
+        let bce = raw
+            .iter()
+            .zip(&targets)
+            .map(|(&x, &t)| {
+                let p = 1.0 / (1.0 + (-x).exp());
+                -(t * p.ln() + (1.0 - t) * (1.0 - p).ln())
+            })
+            .sum::<f64>()
+            / 4.0;
+        assert!((focal.value() - bce).abs() < 1e-12);
+
+        // a confidently correct example gets far less gradient than under BCE
+        let grad_for = |gamma: f64| {
+            let x = Value::new(4.0);
+            losses::focal(std::slice::from_ref(&x), &[1.0], gamma, 1.0).backward();
+            x.0.borrow().grad
+        };
+        assert!(grad_for(2.0).abs() < 0.01 * grad_for(0.0).abs());
+
+        let eps = 1e-6;
+        for (x0, t) in [(0.7, 1.0), (-1.2, 0.0)] {
+            let x = Value::new(x0);
+            losses::focal(std::slice::from_ref(&x), &[t], 2.0, 0.25).backward();
+            let f = |v: f64| losses::focal(&[Value::new(v)], &[t], 2.0, 0.25).value();
+            let numerical = (f(x0 + eps) - f(x0 - eps)) / (2.0 * eps);
+            assert!((numerical - x.0.borrow().grad).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_weighted_losses() {
+        let raw = [0.4, -1.1, 2.0];
+        let targets = [1.0, 0.0, 1.0];
+        let values = || raw.iter().map(|&x| Value::new(x)).collect::<Vec<_>>();
+        let grads = |xs: &[Value]| xs.iter().map(|x| x.0.borrow().grad).collect::<Vec<_>>();
+
+        // unit weights reproduce the plain losses exactly
+        let (a, b) = (values(), values());
+        let plain = losses::mse(&a, &targets);
+        let weighted = losses::mse_weighted(&b, &targets, &[1.0; 3]);
+        assert_eq!(plain.value(), weighted.value());
+        plain.backward();
+        weighted.backward();
+        assert_eq!(grads(&a), grads(&b));
+
+        let (a, b) = (values(), values());
+        let plain = losses::bce_with_logits(&a, &targets);
+        let weighted = losses::bce_with_logits_weighted(&b, &targets, &[1.0; 3]);
+        assert_eq!(plain.value(), weighted.value());
+        plain.backward();
+        weighted.backward();
+        assert_eq!(grads(&a), grads(&b));
+
+        let logits = || vec![values(), values()];
+        let (a, b) = (logits(), logits());
+        let plain = losses::cross_entropy_batch(&a, &[0, 2]);
+        let weighted = losses::cross_entropy_batch_weighted(&b, &[0, 2], &[1.0, 1.0]);
+        assert_eq!(plain.value(), weighted.value());
+        plain.backward();
+        weighted.backward();
+        assert_eq!(grads(&a[1]), grads(&b[1]));
+
+        // a zero weight removes the sample's gradient entirely
+        let xs = values();
+        losses::bce_with_logits_weighted(&xs, &targets, &[1.0, 0.0, 1.0]).backward();
+        assert_eq!(xs[1].0.borrow().grad, 0.0);
+
+        // doubling one weight doubles that sample's share of the parameter gradient
+        let grad_of_w = |weights: &[f64]| {
+            let w = Value::new(0.7);
+            let preds: Vec<Value> = raw.iter().map(|&x| &w * x).collect();
+            losses::mse_weighted(&preds, &targets, weights).backward();
+            w.0.borrow().grad
+        };
+        // (scaled back up by the weight sum to undo the mean's normalisation)
+        let first = grad_of_w(&[1.0, 0.0, 0.0]);
+        let base = grad_of_w(&[1.0, 1.0, 1.0]) * 3.0;
+        let doubled = grad_of_w(&[2.0, 1.0, 1.0]) * 4.0;
+        assert!((doubled - base - first).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_l2_penalty() {
+        let lambda = 0.1;
+        let params = [Value::new(0.5), Value::new(-2.0)];
+        regularization::l2_penalty(&params, lambda).backward();
+        for w in &params {
+            assert!((w.0.borrow().grad - 2.0 * lambda * w.value()).abs() < 1e-12);
+        }
+
+        let model = MLP::new(2, vec![3, 1]);
+        let named = model.named_parameters();
+        let weights_only =
+            regularization::l2_penalty_filtered(&named, 1.0, |name| !name.ends_with(".b"));
+        let expected: f64 = named
+            .iter()
+            .filter(|(name, _)| !name.ends_with(".b"))
+            .map(|(_, w)| w.value() * w.value())
+            .sum();
+        assert!((weights_only.value() - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_l1_penalty_sparsifies() {
+        let params = [Value::new(0.5), Value::new(-2.0)];
+        let net = regularization::elastic_net(&params, 0.1, 0.2);
+        let expected = 0.1 * 2.5 + 0.2 * 4.25;
+        assert!((net.value() - expected).abs() < 1e-12);
+
+        // x2 = x1 / 2 carries no extra information; under L1 it is cheaper
+        // to explain the target through x1 alone, so w2 is driven to zero
+        let xs: Vec<f64> = (-5..=5).map(|i| i as f64 * 0.2).collect();
+        let w = [Value::new(0.5), Value::new(0.5)];
+        let mut optimizer = SGD::new(w.to_vec(), 0.05);
+        for _ in 0..2000 {
+            let preds: Vec<Value> = xs
+                .iter()
+                .map(|&x| &(&w[0] * x) + &(&w[1] * (0.5 * x)))
+                .collect();
+            let targets: Vec<f64> = xs.iter().map(|&x| 3.0 * x).collect();
+            let loss = &losses::mse(&preds, &targets) + &regularization::l1_penalty(&w, 0.1);
+            w.iter().for_each(|p| p.0.borrow_mut().grad = 0.0);
+            loss.backward();
+            optimizer.step();
+        }
+        assert!(w[1].value().abs() < 0.02);
+        assert!(w[0].value() > 2.5);
+    }
+
+    #[test]
+    fn test_l2_penalty_shrinks_xor_weights() {
+        let inputs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let targets = [0.0, 1.0, 1.0, 0.0];
+        let train = |lambda: f64| {
+            let model = MLP::builder()
+                .input(2)
+                .dense(8, Activation::Tanh)
+                .dense(1, Activation::Linear)
+                .seed(11)
+                .build()
+                .unwrap();
+            let mut optimizer = SGD::new(model.parameters(), 0.05);
+            for _ in 0..500 {
+                let preds: Vec<Value> = inputs
+                    .iter()
+                    .map(|x| model.forward(&[Value::new(x[0]), Value::new(x[1])])[0].clone())
+                    .collect();
+                let loss = &losses::mse(&preds, &targets)
+                    + &regularization::l2_penalty(&model.parameters(), lambda);
+                model.zero_grad();
+                loss.backward();
+                optimizer.step();
+            }
+            let fit: Vec<f64> = inputs
+                .iter()
+                .map(|x| model.forward(&[Value::new(x[0]), Value::new(x[1])])[0].value())
+                .collect();
+            (fit, model.param_norm())
+        };
+
+        let (_, plain_norm) = train(0.0);
+        let (fit, reg_norm) = train(1e-3);
+        for (pred, target) in fit.iter().zip(&targets) {
+            assert!((pred - target).abs() < 0.2);
+        }
+        assert!(reg_norm < plain_norm);
+    }
+
+    #[test]
+    fn test_data_loader() {
+        let inputs: Vec<Vec<f64>> = (0..10).map(|i| vec![i as f64, -(i as f64)]).collect();
+        let targets: Vec<f64> = (0..10).map(|i| i as f64 * 0.5).collect();
+        let mut loader = DataLoader::with_seed(inputs.clone(), targets.clone(), 3, 5);
+
+        let epoch = |loader: &mut DataLoader| -> Vec<usize> {
+            let mut seen = vec![];
+            for batch in loader.batches() {
+                assert!(batch.indices.len() <= 3);
+                for (k, &i) in batch.indices.iter().enumerate() {
+                    assert_eq!(batch.inputs[k][0].value(), i as f64);
+                    assert_eq!(batch.targets[k], i as f64 * 0.5);
+                }
+                seen.extend(batch.indices);
+            }
+            seen
+        };
+
+        let first = epoch(&mut loader);
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, (0..10).collect::<Vec<_>>());
+        // each epoch reshuffles
+        assert_ne!(first, epoch(&mut loader));
+
+        let mut again = DataLoader::with_seed(inputs, targets, 3, 5);
+        assert_eq!(first, epoch(&mut again));
+    }
+
+    #[test]
+    fn test_step_lr() {
+        let sched = StepLR::new(0.8, 10, 0.5);
+        let mut opt = SGD::new(vec![Value::new(0.0)], 0.8);
+        let mut seen = vec![];
+        let mut sched_driven = StepLR::new(0.8, 10, 0.5);
+        for _ in 0..35 {
+            seen.push(Optimizer::lr(&opt));
+            sched_driven.step(&mut opt);
+        }
+        let expected: Vec<f64> = (0..35).map(|e| [0.8, 0.4, 0.2, 0.1][e / 10]).collect();
+        assert_eq!(seen, expected);
+        assert_eq!((0..35).map(|e| sched.lr(e)).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_cosine_annealing_lr() {
+        let sched = CosineAnnealingLR::new(0.1, 0.001, 20);
+        assert_eq!(sched.lr(0), 0.1);
+        assert!((sched.lr(20) - 0.001).abs() < 1e-12);
+        assert!((sched.lr(10) - 0.0505).abs() < 1e-12);
+        assert!((sched.lr(35) - 0.001).abs() < 1e-12);
+        assert!((1..=20).all(|e| sched.lr(e) < sched.lr(e - 1)));
+
+        // cycles of 10, then 20 epochs
+        let sched = CosineAnnealingLR::new(0.1, 0.0, 10).with_restarts(2);
+        assert!(sched.lr(9) < 0.01);
+        assert_eq!(sched.lr(10), 0.1);
+        assert!((sched.lr(20) - 0.05).abs() < 1e-12);
+        assert_eq!(sched.lr(30), 0.1);
+
+        let mut opt = SGD::new(vec![Value::new(0.0)], 0.1);
+        let mut driven = CosineAnnealingLR::new(0.1, 0.0, 10).with_restarts(2);
+        for e in 1..=30 {
+            driven.step(&mut opt);
+            assert_eq!(opt.lr(), sched.lr(e));
+        }
+    }
+
+    #[test]
+    fn test_warmup() {
+        let sched = Warmup::new(Box::new(StepLR::new(0.1, 10, 0.5)), 5);
+        assert_eq!(sched.lr(0), 0.0);
+        assert!((sched.lr(2) - 0.04).abs() < 1e-12);
+        assert_eq!(sched.lr(5), 0.1);
+        assert_eq!(sched.lr(15), 0.05);
+
+        let floored = Warmup::new(Box::new(StepLR::new(0.1, 10, 0.5)), 4).with_floor(0.02);
+        assert_eq!(floored.lr(0), 0.02);
+        assert!((floored.lr(2) - 0.06).abs() < 1e-12);
+
+        // ramp over 10 steps, then a 20-step cosine from 0.1 down to 0
+        let cosine = CosineAnnealingLR::new(0.1, 0.0, 20);
+        let sched = Warmup::new(Box::new(CosineAnnealingLR::new(0.1, 0.0, 20)), 10);
+        for (step, expected) in [(0, 0.0), (5, 0.05), (10, 0.1), (20, 0.05), (30, 0.0)] {
+            assert!((sched.lr(step) - expected).abs() < 1e-12, "step {}", step);
+        }
+        assert_eq!(sched.lr(17), cosine.lr(7));
+
+        let mut opt = Adam::new(vec![Value::new(0.0)], 0.0);
+        let mut driven = Warmup::new(Box::new(CosineAnnealingLR::new(0.1, 0.0, 20)), 10);
+        for step in 1..=30 {
+            driven.step(&mut opt);
+            assert_eq!(opt.lr(), sched.lr(step));
+        }
+    }
+
+    #[test]
+    fn test_cyclical_lr() {
+        let sched = CyclicalLR::new(0.01, 0.1, 4, 6);
+        assert_eq!(sched.period(), 10);
+        for cycle in 0..3 {
+            let start = cycle * sched.period();
+            assert_eq!(sched.lr(start), 0.01);
+            assert_eq!(sched.lr(start + 4), 0.1);
+            assert!((sched.lr(start + 2) - 0.055).abs() < 1e-12);
+            assert!((sched.lr(start + 7) - 0.055).abs() < 1e-12);
+        }
+        let lrs: Vec<f64> = (0..30).map(|e| sched.lr(e)).collect();
+        assert!(
+            lrs[..10]
+                .iter()
+                .zip(&lrs[10..20])
+                .all(|(a, b)| (a - b).abs() < 1e-15)
+        );
+
+        let mut opt = SGD::new(vec![Value::new(0.0)], 0.01);
+        let mut driven = CyclicalLR::new(0.01, 0.1, 4, 6);
+        for _ in 0..10 {
+            driven.step(&mut opt);
+        }
+        assert!((opt.lr() - 0.01).abs() < 1e-15);
+    }
+
+    #[test]
+    fn test_cosine_warm_restarts() {
+        let sched = CosineAnnealingWarmRestarts::new(0.1, 0.001, 5, 2);
+        // cycles of 5, 10, 20, 40 steps
+        assert_eq!(sched.restarts(80), vec![5, 15, 35, 75]);
+        for &r in &sched.restarts(80) {
+            assert_eq!(sched.lr(r), 0.1);
+            assert!(sched.lr(r - 1) < 0.02, "step {}", r - 1);
+        }
+        assert!((sched.lr(25) - 0.0505).abs() < 1e-12);
+
+        let mut opt = SGD::new(vec![Value::new(0.0)], 0.1);
+        let mut driven = CosineAnnealingWarmRestarts::new(0.1, 0.001, 5, 2);
+        for step in 1..=40 {
+            driven.step(&mut opt);
+            assert_eq!(opt.lr(), sched.lr(step));
+        }
+    }
+
+    #[test]
+    fn test_step_lr_xor() {
+        let xs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let ys = [-1.0, 1.0, 1.0, -1.0];
+        let train = |mut sched: Option<StepLR>| {
+            let model = MLP::new_seeded(2, vec![4, 1], 7);
+            let mut sgd = SGD::new(model.parameters(), 0.45);
+            let mut loss = 0.0;
+            for _ in 0..400 {
+                model.zero_grad();
+                let preds: Vec<Value> = xs
+                    .iter()
+                    .map(|x| model.call(&x.map(Value::new))[0].clone())
+                    .collect();
+                let l = losses::mse(&preds, &ys);
+                l.backward();
+                sgd.step();
+                loss = l.value();
+                if let Some(s) = sched.as_mut() {
+                    s.step(&mut sgd);
+                }
+            }
+            loss
+        };
+        // 0.45 is big enough that the constant rate stalls bouncing around
+        // the minimum, which the decayed rate then settles into
+        let constant = train(None);
+        let decayed = train(Some(StepLR::new(0.45, 100, 0.5)));
+        assert!(
+            decayed <= constant,
+            "decayed {} vs constant {}",
+            decayed,
+            constant
+        );
+    }
+
+    #[test]
+    fn test_swa_averaging() {
+        let w = vec![Value::new(0.1), Value::new(-0.7)];
+        let mut swa = Swa::new(w.clone(), 2);
+        assert!(!swa.update(1));
+        for epoch in 2..7 {
+            assert!(swa.update(epoch));
+        }
+        assert_eq!(swa.count(), 5);
+        assert_eq!(swa.average(), &[0.1, -0.7]);
+
+        let mut swa = Swa::new(w.clone(), 0);
+        for (epoch, (a, b)) in [(0.25, -0.75), (0.75, 1.25)].into_iter().enumerate() {
+            w[0].0.borrow_mut().data = a;
+            w[1].0.borrow_mut().data = b;
+            swa.update(epoch);
+        }
+        assert_eq!(swa.average(), &[0.5, 0.25]);
+        swa.apply();
+        assert_eq!(w[0].value(), 0.5);
+        swa.restore();
+        assert_eq!(w[0].value(), 0.75);
+    }
+
+    #[test]
+    fn test_swa_xor() {
+        let xs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let ys = [-1.0, 1.0, 1.0, -1.0];
+        let model = MLP::new_seeded(2, vec![4, 1], 7);
+        let predict = || -> Vec<Value> {
+            xs.iter()
+                .map(|x| model.call(&x.map(Value::new))[0].clone())
+                .collect()
+        };
+        let mut sgd = SGD::new(model.parameters(), 0.45);
+        let mut sched = SwaLR::new(Box::new(StepLR::new(0.45, 100, 0.5)), 300, 0.05)
+            .with_cycle(Box::new(CyclicalLR::new(0.01, 0.05, 5, 5)));
+        let mut swa = Swa::new(model.parameters(), 300);
+        for epoch in 0..400 {
+            model.zero_grad();
+            losses::mse(&predict(), &ys).backward();
+            sgd.step();
+            swa.update(epoch);
+            sched.step(&mut sgd);
+        }
+        assert_eq!(swa.count(), 100);
+        assert!(sgd.lr() >= 0.01 && sgd.lr() <= 0.05);
+
+        swa.apply();
+        for (p, y) in predict().iter().zip(ys) {
+            assert!(
+                p.value().is_finite() && p.value() * y > 0.5,
+                "{} vs {}",
+                p.value(),
+                y
+            );
+        }
+        swa.restore();
+    }
+
+    #[test]
+    fn test_metrics() {
+        let preds = [0.9, 0.2, 0.6, 0.4];
+        let targets = [1.0, 0.0, 0.0, 0.0];
+        assert_eq!(crate::metrics::accuracy(&preds, &targets, 0.5), 0.75);
+        assert!((crate::metrics::mse(&preds, &targets) - 0.1425).abs() < 1e-12);
+        assert_eq!(crate::metrics::r2_score(&targets, &targets), 1.0);
+        let mean = [0.25; 4];
+        assert_eq!(crate::metrics::r2_score(&mean, &targets), 0.0);
+
+        let xs = [[0.0, 0.0], [0.0, 1.0], [1.0, 0.0], [1.0, 1.0]];
+        let ys = [-1.0, 1.0, 1.0, -1.0];
+        let model = MLP::new_seeded(2, vec![4, 1], 7);
+        let mut sgd = SGD::new(model.parameters(), 0.2);
+        let predict = || -> Vec<Value> {
+            xs.iter()
+                .map(|x| model.call(&x.map(Value::new))[0].clone())
+                .collect()
+        };
+        for _ in 0..300 {
+            model.zero_grad();
+            losses::mse(&predict(), &ys).backward();
+            sgd.step();
+        }
+        let preds: Vec<f64> = predict().iter().map(|p| p.value()).collect();
+        assert_eq!(crate::metrics::accuracy(&preds, &ys, 0.0), 1.0);
+        assert!(crate::metrics::r2_score(&preds, &ys) > 0.9);
+    }
+
+    #[test]
+    fn test_trainer_fit_with_validation() {
+        let xs: Vec<Vec<f64>> = (0..20).map(|i| vec![i as f64 / 10.0 - 1.0]).collect();
+        let ys: Vec<f64> = xs.iter().map(|x| 0.8 * x[0] - 0.3).collect();
+        let model = MLP::builder()
+            .input(1)
+            .dense(8, Activation::Tanh)
+            .dropout(0.2)
+            .dense(1, Activation::Linear)
+            .seed(42)
+            .build()
+            .unwrap();
+        let mut opt = SGD::new(model.parameters(), 0.1);
+        let mut trainer = Trainer::new(&model, &mut opt, losses::mse);
+        let history = trainer.fit_split(&xs, &ys, 100, 0.25);
+
+        assert_eq!(history.train_loss.len(), 100);
+        assert_eq!(history.val_loss.len(), 100);
+        assert!(history.train_loss[99] < history.train_loss[0]);
+        // eval mode disables dropout, so re-scoring the held-out quarter is
+        // deterministic and matches the last recorded validation loss
+        let (val_x, val_y) = (&xs[15..], &ys[15..]);
+        let val = trainer.evaluate(val_x, val_y);
+        assert_eq!(val, history.val_loss[99]);
+        assert_eq!(trainer.evaluate(val_x, val_y), val);
+    }
+
+    #[test]
+    fn test_xor_training_showcase() {
+        let model = MLP::new_seeded(2, vec![4, 4, 1], 1);
+        let mut optimizer = SGD::new(model.parameters(), 0.1);
 
         // XOR dataset
-        let inputs = vec![
+        let inputs = [
             vec![Value::new(0.0), Value::new(0.0)],
             vec![Value::new(0.0), Value::new(1.0)],
             vec![Value::new(1.0), Value::new(0.0)],
             vec![Value::new(1.0), Value::new(1.0)],
         ];
-        let targets = vec![0.0, 1.0, 1.0, 0.0];
+        let targets = [0.0, 1.0, 1.0, 0.0];
 
         println!("Starting XOR Training...");
 
@@ -152,7 +3442,7 @@ mod tests {
 
             for (x, y_true) in inputs.iter().zip(targets.iter()) {
                 // Forward pass
-                let y_pred = &model.call(x.clone())[0];
+                let y_pred = &model.call(x)[0];
 
                 // Mean Squared Error Loss: (pred - true)^2
                 let diff = y_pred - &Value::new(*y_true);
@@ -174,7 +3464,7 @@ mod tests {
 
         // Final Verification
         for (x, y_true) in inputs.iter().zip(targets.iter()) {
-            let pred = model.call(x.clone())[0].value();
+            let pred = model.call(x)[0].value();
             println!(
                 "In: {:?} Target: {} Pred: {:.4}",
                 x.iter().map(|v| v.value()).collect::<Vec<_>>(),
@@ -185,7 +3475,7 @@ mod tests {
         }
 
         // Visualize the final learned state of the computational graph
-        let final_pred = &model.call(inputs[1].clone())[0];
+        let final_pred = &model.call(&inputs[1])[0];
         final_pred.draw();
     }
 }
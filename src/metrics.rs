@@ -0,0 +1,51 @@
+//! Evaluation metrics on plain predictions (e.g. `value()`s after a forward
+//! pass); nothing here touches the graph.
+
+fn check_len(name: &str, preds: &[f64], targets: &[f64]) {
+    assert_eq!(
+        preds.len(),
+        targets.len(),
+        "{}: got {} predictions but {} targets",
+        name,
+        preds.len(),
+        targets.len()
+    );
+}
+
+pub fn mse(preds: &[f64], targets: &[f64]) -> f64 {
+    check_len("mse", preds, targets);
+    let sum: f64 = preds
+        .iter()
+        .zip(targets)
+        .map(|(p, t)| (p - t).powi(2))
+        .sum();
+    sum / preds.len() as f64
+}
+
+/// Fraction of samples on the same side of `threshold` as their target,
+/// e.g. `0.5` for sigmoid outputs against 0/1 labels or `0.0` for tanh
+/// outputs against ±1.
+pub fn accuracy(preds: &[f64], targets: &[f64], threshold: f64) -> f64 {
+    check_len("accuracy", preds, targets);
+    let correct = preds
+        .iter()
+        .zip(targets)
+        .filter(|&(p, t)| (*p >= threshold) == (*t >= threshold))
+        .count();
+    correct as f64 / preds.len() as f64
+}
+
+/// Coefficient of determination, `1 - SS_res / SS_tot`: 1 for a perfect
+/// fit, 0 for always predicting the target mean. Targets with no variance
+/// give `NaN` (or `-inf` if the predictions miss them).
+pub fn r2_score(preds: &[f64], targets: &[f64]) -> f64 {
+    check_len("r2_score", preds, targets);
+    let mean = targets.iter().sum::<f64>() / targets.len() as f64;
+    let ss_res: f64 = preds
+        .iter()
+        .zip(targets)
+        .map(|(p, t)| (t - p).powi(2))
+        .sum();
+    let ss_tot: f64 = targets.iter().map(|t| (t - mean).powi(2)).sum();
+    1.0 - ss_res / ss_tot
+}
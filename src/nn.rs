@@ -1,98 +1,1012 @@
+pub mod losses;
+pub mod regularization;
 
-use rand::Rng;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fmt;
+use std::rc::Rc;
 
-use crate::engine::Value;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::builder::MLPBuilder;
+use crate::engine::{Value, softmax};
+use crate::init::{Init, orthogonal, standard_normal};
 
 pub trait Module {
+    fn forward(&self, x: &[Value]) -> Vec<Value>;
+    /// `forward` on plain arrays, without building a graph (see `no_grad`).
+    #[cfg(feature = "ndarray")]
+    fn forward_array(&self, x: &ndarray::Array1<f64>) -> ndarray::Array1<f64> {
+        crate::engine::no_grad(|| {
+            crate::array::to_array(&self.forward(&crate::array::to_values(x)))
+        })
+    }
     fn parameters(&self) -> Vec<Value>;
+    /// Parameters paired with a dotted path such as `layer0.neuron1.w2`.
+    /// Modules without their own naming scheme fall back to `p0`, `p1`, ...
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.parameters()
+            .into_iter()
+            .enumerate()
+            .map(|(i, p)| (format!("p{}", i), p))
+            .collect()
+    }
     fn zero_grad(&self) {
         for p in self.parameters() {
             p.0.borrow_mut().grad = 0.0;
         }
     }
+    /// Switches between training and inference behaviour. Only modules with
+    /// mode-dependent forward passes (e.g. `Dropout`) need to override this.
+    fn set_training(&self, _training: bool) {}
+    fn freeze(&self) {
+        for p in self.parameters() {
+            p.0.borrow_mut().requires_grad = false;
+        }
+    }
+    fn unfreeze(&self) {
+        for p in self.parameters() {
+            p.0.borrow_mut().requires_grad = true;
+        }
+    }
+    fn parameters_snapshot(&self) -> Vec<f64> {
+        self.parameters().iter().map(|p| p.value()).collect()
+    }
+    /// Parameter data in `parameters()` order; same as `parameters_snapshot`,
+    /// paired with `load_state` for in-memory checkpoints.
+    fn state(&self) -> Vec<f64> {
+        self.parameters_snapshot()
+    }
+    /// Writes `data` back into the parameters, in `parameters()` order.
+    /// Nothing is written if the length is wrong.
+    fn load_state(&self, data: &[f64]) -> Result<(), StateError> {
+        let params = self.parameters();
+        if params.len() != data.len() {
+            return Err(StateError::LengthMismatch {
+                expected: params.len(),
+                got: data.len(),
+            });
+        }
+        for (p, &d) in params.iter().zip(data) {
+            p.0.borrow_mut().data = d;
+        }
+        Ok(())
+    }
+    /// Writes a PNG bar chart of each parameter's gradient magnitude, in
+    /// `parameters()` order, to spot dead or exploding weights at a glance.
+    fn plot_grads(&self, path: &str) -> std::io::Result<()> {
+        let grads: Vec<f64> = self
+            .parameters()
+            .iter()
+            .map(|p| p.0.borrow().grad)
+            .collect();
+        crate::plot::plot_grads(&grads, path)
+    }
+    fn grad_norm(&self) -> f64 {
+        self.parameters()
+            .iter()
+            .map(|p| p.0.borrow().grad.powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+    fn param_norm(&self) -> f64 {
+        self.parameters()
+            .iter()
+            .map(|p| p.value().powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StateError {
+    LengthMismatch { expected: usize, got: usize },
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::LengthMismatch { expected, got } => {
+                write!(f, "expected {} parameter values, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Per-parameter change between two `parameters_snapshot`s (`after - before`).
+pub fn param_diff(before: &[f64], after: &[f64]) -> Vec<f64> {
+    assert_eq!(
+        before.len(),
+        after.len(),
+        "param_diff: snapshot length mismatch"
+    );
+    before.iter().zip(after).map(|(b, a)| a - b).collect()
+}
+
+/// Element-wise mean of several same-shaped models' parameters, ready for
+/// `load_state`. Errors if any model's parameter count differs from the first.
+pub fn average_models(models: &[&dyn Module]) -> Result<Vec<f64>, StateError> {
+    let Some((first, rest)) = models.split_first() else {
+        return Ok(vec![]);
+    };
+    let mut sum = first.state();
+    for model in rest {
+        let state = model.state();
+        if state.len() != sum.len() {
+            return Err(StateError::LengthMismatch {
+                expected: sum.len(),
+                got: state.len(),
+            });
+        }
+        sum.iter_mut().zip(state).for_each(|(s, x)| *s += x);
+    }
+    let n = models.len() as f64;
+    Ok(sum.into_iter().map(|s| s / n).collect())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Activation {
+    Linear,
+    Tanh,
+    Relu,
+    Sigmoid,
+    /// Applied across a whole layer's outputs; a single neuron stays linear.
+    Softmax,
+}
+
+impl Activation {
+    pub fn apply(&self, x: &Value) -> Value {
+        match self {
+            Activation::Linear | Activation::Softmax => x.clone(),
+            Activation::Tanh => x.tanh(),
+            Activation::Relu => x.relu(),
+            Activation::Sigmoid => x.sigmoid(),
+        }
+    }
+}
+
+fn tanh_if(nonlin: bool) -> Activation {
+    if nonlin {
+        Activation::Tanh
+    } else {
+        Activation::Linear
+    }
 }
 
 pub struct Neuron {
     w: Vec<Value>,
     b: Value,
-    nonlin: bool,
+    act: Activation,
 }
 
 impl Neuron {
     pub fn new(nin: u64, nonlin: bool) -> Self {
-        let mut rng = rand::thread_rng();
-        let w: Vec<Value> = (0..nin)
-            .map(|_| Value::new(rng.gen_range(-1.0..1.0)))
-            .collect();
-        let b = Value::new(0.0);
-        Self { w, b, nonlin }
+        Self::with_activation(nin, tanh_if(nonlin))
+    }
+
+    /// Like [`Neuron::new`], but draws the weights from `rng`.
+    pub fn new_seeded(nin: u64, nonlin: bool, rng: &mut impl Rng) -> Self {
+        Self::with_init_rng(nin, 1, tanh_if(nonlin), Init::default(), rng)
+    }
+
+    pub fn with_activation(nin: u64, act: Activation) -> Self {
+        Self::with_init(nin, 1, act, Init::default_for(act))
+    }
+
+    pub fn with_init(nin: u64, fan_out: u64, act: Activation, init: Init) -> Self {
+        Self::with_init_rng(nin, fan_out, act, init, &mut rand::rng())
+    }
+
+    pub fn with_init_rng(
+        nin: u64,
+        fan_out: u64,
+        act: Activation,
+        init: Init,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Self::with_inits_rng(nin, fan_out, act, init, Init::Constant(0.0), rng)
+    }
+
+    /// Like [`Neuron::with_init`], but with a scheme for the bias too instead
+    /// of the usual zero, e.g. `Init::Constant(0.01)` to keep relu units alive.
+    pub fn with_inits(
+        nin: u64,
+        fan_out: u64,
+        act: Activation,
+        weight_init: Init,
+        bias_init: Init,
+    ) -> Self {
+        Self::with_inits_rng(nin, fan_out, act, weight_init, bias_init, &mut rand::rng())
+    }
+
+    pub fn with_inits_rng(
+        nin: u64,
+        fan_out: u64,
+        act: Activation,
+        weight_init: Init,
+        bias_init: Init,
+        rng: &mut impl Rng,
+    ) -> Self {
+        weight_init.validate();
+        bias_init.validate();
+        let w: Vec<Value> = match weight_init {
+            Init::Orthogonal { gain } => orthogonal(rng, 1, nin as usize, gain)[0]
+                .iter()
+                .map(|&x| Value::new(x))
+                .collect(),
+            _ => (0..nin)
+                .map(|_| Value::new(weight_init.sample(rng, nin, fan_out)))
+                .collect(),
+        };
+        let b = Value::new(bias_init.sample(rng, nin, fan_out));
+        Self { w, b, act }
+    }
+
+    /// Builds a neuron around existing parameter nodes, e.g. to tie weights
+    /// with another module.
+    pub fn from_weights(w: Vec<Value>, b: Value, act: Activation) -> Self {
+        Self { w, b, act }
+    }
+
+    pub fn weights(&self) -> &[Value] {
+        &self.w
+    }
+
+    pub fn bias(&self) -> &Value {
+        &self.b
     }
 
     pub fn call(&self, x: &[Value]) -> Value {
-        let act = self
-            .w
-            .iter()
-            .zip(x.iter())
-            .map(|(wi, xi)| wi * xi)
-            .fold(self.b.clone(), |acc, val| &acc + &val);
+        let act = Value::linear(x, &self.w, &self.b);
+        self.act.apply(&act)
+    }
 
-        if self.nonlin { act.tanh() } else { act }
+    /// Applies the neuron to each timestep's input, one output per step.
+    /// Every step uses the same weight nodes, so backward sums their
+    /// gradients over the sequence.
+    pub fn call_sequence(&self, sequence: &[Vec<Value>]) -> Vec<Value> {
+        sequence.iter().map(|x| self.call(x)).collect()
     }
 }
 
 impl Module for Neuron {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        vec![self.call(x)]
+    }
+
     fn parameters(&self) -> Vec<Value> {
         let mut p = self.w.clone();
         p.push(self.b.clone());
         p
     }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        let mut named: Vec<(String, Value)> = self
+            .w
+            .iter()
+            .enumerate()
+            .map(|(i, w)| (format!("w{}", i), w.clone()))
+            .collect();
+        named.push(("b".to_string(), self.b.clone()));
+        named
+    }
 }
 
 pub struct Layer {
     neurons: Vec<Neuron>,
+    act: Activation,
 }
 
 impl Layer {
     pub fn new(nin: u64, nout: u64, nonlin: bool) -> Self {
-        let neurons = (0..nout).map(|_| Neuron::new(nin, nonlin)).collect();
-        Self { neurons }
+        Self::with_activation(nin, nout, tanh_if(nonlin))
+    }
+
+    /// Like [`Layer::new`], but draws the weights from `rng`.
+    pub fn new_seeded(nin: u64, nout: u64, nonlin: bool, rng: &mut impl Rng) -> Self {
+        Self::with_init_rng(nin, nout, tanh_if(nonlin), Init::default(), rng)
+    }
+
+    pub fn with_activation(nin: u64, nout: u64, act: Activation) -> Self {
+        Self::with_init(nin, nout, act, Init::default_for(act))
+    }
+
+    pub fn with_init(nin: u64, nout: u64, act: Activation, init: Init) -> Self {
+        Self::with_init_rng(nin, nout, act, init, &mut rand::rng())
+    }
+
+    pub fn with_init_rng(
+        nin: u64,
+        nout: u64,
+        act: Activation,
+        init: Init,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Self::with_inits_rng(nin, nout, act, init, Init::Constant(0.0), rng)
+    }
+
+    /// See [`Neuron::with_inits`].
+    pub fn with_inits(
+        nin: u64,
+        nout: u64,
+        act: Activation,
+        weight_init: Init,
+        bias_init: Init,
+    ) -> Self {
+        Self::with_inits_rng(nin, nout, act, weight_init, bias_init, &mut rand::rng())
+    }
+
+    pub fn with_inits_rng(
+        nin: u64,
+        nout: u64,
+        act: Activation,
+        weight_init: Init,
+        bias_init: Init,
+        rng: &mut impl Rng,
+    ) -> Self {
+        weight_init.validate();
+        bias_init.validate();
+        if let Init::Orthogonal { gain } = weight_init {
+            // The rows have to be orthogonal to each other, so draw the whole
+            // matrix and hand one row to each neuron.
+            let neurons = orthogonal(rng, nout as usize, nin as usize, gain)
+                .into_iter()
+                .map(|row| {
+                    let w = row.into_iter().map(Value::new).collect();
+                    let b = Value::new(bias_init.sample(rng, nin, nout));
+                    Neuron::from_weights(w, b, act)
+                })
+                .collect();
+            return Self { neurons, act };
+        }
+        let neurons = (0..nout)
+            .map(|_| Neuron::with_inits_rng(nin, nout, act, weight_init, bias_init, rng))
+            .collect();
+        Self { neurons, act }
+    }
+
+    pub fn from_weights(weights: Vec<Vec<Value>>, biases: Vec<Value>, act: Activation) -> Self {
+        assert_eq!(weights.len(), biases.len());
+        let neurons = weights
+            .into_iter()
+            .zip(biases)
+            .map(|(w, b)| Neuron::from_weights(w, b, act))
+            .collect();
+        Self { neurons, act }
+    }
+
+    /// A layer whose parameters come from `f(neuron, index)`: indices
+    /// `0..nin` are the weights and `nin` is the bias.
+    pub fn from_fn(nin: u64, nout: u64, act: Activation, f: impl Fn(usize, usize) -> f64) -> Self {
+        let nin = nin as usize;
+        let neurons = (0..nout as usize)
+            .map(|j| {
+                let w = (0..nin).map(|i| Value::new(f(j, i))).collect();
+                Neuron::from_weights(w, Value::new(f(j, nin)), act)
+            })
+            .collect();
+        Self { neurons, act }
+    }
+
+    /// A layer mapping `other`'s outputs back to its inputs using the
+    /// transpose of `other`'s weight matrix. The weights are the same nodes,
+    /// so training either layer updates both; biases are fresh zeros.
+    pub fn tied_transpose(other: &Layer, act: Activation) -> Self {
+        let nin = other.fan_in();
+        let weights = (0..nin)
+            .map(|i| other.neurons.iter().map(|n| n.w[i].clone()).collect())
+            .collect();
+        let biases = (0..nin).map(|_| Value::new(0.0)).collect();
+        Self::from_weights(weights, biases, act)
+    }
+
+    pub fn neurons(&self) -> &[Neuron] {
+        &self.neurons
+    }
+
+    /// Inputs per neuron; 0 for a layer with no neurons.
+    fn fan_in(&self) -> usize {
+        self.neurons.first().map_or(0, |n| n.w.len())
     }
 
     pub fn call(&self, x: &[Value]) -> Vec<Value> {
-        self.neurons.iter().map(|n| n.call(x)).collect()
+        let outs: Vec<Value> = self.neurons.iter().map(|n| n.call(x)).collect();
+        if self.act == Activation::Softmax {
+            softmax(&outs)
+        } else {
+            outs
+        }
     }
 }
 
 impl Module for Layer {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.call(x)
+    }
+
     fn parameters(&self) -> Vec<Value> {
         self.neurons.iter().flat_map(|n| n.parameters()).collect()
     }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| prefixed(format!("neuron{}", i), n.named_parameters()))
+            .collect()
+    }
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub struct MLP {
     layers: Vec<Layer>,
 }
 
 impl MLP {
     pub fn new(nin: u64, nouts: Vec<u64>) -> Self {
+        Self::new_with_output(nin, nouts, Activation::Tanh, Activation::Linear)
+    }
+
+    pub fn new_with_output(
+        nin: u64,
+        nouts: Vec<u64>,
+        hidden_act: Activation,
+        output_act: Activation,
+    ) -> Self {
+        Self::layers_with(
+            nin,
+            nouts,
+            hidden_act,
+            output_act,
+            Init::default_for,
+            &mut rand::rng(),
+        )
+    }
+
+    /// Like [`MLP::new`], but every weight comes from a `StdRng` seeded with
+    /// `seed`, so the same seed always builds the same network.
+    pub fn new_seeded(nin: u64, nouts: Vec<u64>, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::new_with_init_rng(
+            nin,
+            nouts,
+            Activation::Tanh,
+            Activation::Linear,
+            Init::default(),
+            &mut rng,
+        )
+    }
+
+    pub fn new_with_init(
+        nin: u64,
+        nouts: Vec<u64>,
+        hidden_act: Activation,
+        output_act: Activation,
+        init: Init,
+    ) -> Self {
+        Self::new_with_init_rng(nin, nouts, hidden_act, output_act, init, &mut rand::rng())
+    }
+
+    pub fn new_with_init_rng(
+        nin: u64,
+        nouts: Vec<u64>,
+        hidden_act: Activation,
+        output_act: Activation,
+        init: Init,
+        rng: &mut impl Rng,
+    ) -> Self {
+        Self::layers_with(nin, nouts, hidden_act, output_act, |_| init, rng)
+    }
+
+    fn layers_with(
+        nin: u64,
+        nouts: Vec<u64>,
+        hidden_act: Activation,
+        output_act: Activation,
+        init_for: impl Fn(Activation) -> Init,
+        rng: &mut impl Rng,
+    ) -> Self {
+        let mut sz = vec![nin];
+        sz.extend(&nouts);
+        let layers = (0..nouts.len())
+            .map(|i| {
+                let act = if i == nouts.len() - 1 {
+                    output_act
+                } else {
+                    hidden_act
+                };
+                Layer::with_init_rng(sz[i], sz[i + 1], act, init_for(act), rng)
+            })
+            .collect();
+        Self { layers }
+    }
+
+    /// An MLP whose parameters come from `f(layer, neuron, index)`, with the
+    /// same index convention as [`Layer::from_fn`]. Fully deterministic, so
+    /// handy for tests and for hand-crafted initializations.
+    pub fn from_fn(
+        nin: u64,
+        nouts: Vec<u64>,
+        hidden_act: Activation,
+        output_act: Activation,
+        f: impl Fn(usize, usize, usize) -> f64,
+    ) -> Self {
         let mut sz = vec![nin];
         sz.extend(&nouts);
         let layers = (0..nouts.len())
-            .map(|i| Layer::new(sz[i], sz[i + 1], i != nouts.len() - 1))
+            .map(|l| {
+                let act = if l == nouts.len() - 1 {
+                    output_act
+                } else {
+                    hidden_act
+                };
+                Layer::from_fn(sz[l], sz[l + 1], act, |j, i| f(l, j, i))
+            })
             .collect();
         Self { layers }
     }
 
-    pub fn call(&self, mut x: Vec<Value>) -> Vec<Value> {
-        for layer in &self.layers {
-            x = layer.call(&x);
+    pub fn builder() -> MLPBuilder {
+        MLPBuilder::new()
+    }
+
+    pub fn from_layers(layers: Vec<Layer>) -> Self {
+        Self { layers }
+    }
+
+    pub fn layers(&self) -> &[Layer] {
+        &self.layers
+    }
+
+    /// L2 norm of each layer's parameter grads, input layer first. Useful
+    /// after `backward` to see which layers are actually learning.
+    pub fn per_layer_grad_norm(&self) -> Vec<f64> {
+        self.layers.iter().map(|l| l.grad_norm()).collect()
+    }
+
+    pub fn layer(&self, i: usize) -> Option<&Layer> {
+        self.layers.get(i)
+    }
+
+    /// Graphviz DOT of the architecture: an input node, then one node per
+    /// layer labelled with its `in -> out` sizes and activation.
+    pub fn architecture_dot(&self) -> String {
+        let mut dot = String::from("digraph mlp {\n    rankdir=LR;\n    node [shape=box];\n");
+        let nin = self.layers.first().map_or(0, |l| l.fan_in());
+        dot.push_str(&format!("    input [label=\"input\\n{}\"];\n", nin));
+        let mut prev = "input".to_string();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let name = format!("layer{}", i);
+            dot.push_str(&format!(
+                "    {} [label=\"{}\\n{} -> {}\\n{:?}\"];\n",
+                name,
+                name,
+                layer.fan_in(),
+                layer.neurons.len(),
+                layer.act
+            ));
+            dot.push_str(&format!("    {} -> {};\n", prev, name));
+            prev = name;
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    pub fn call(&self, x: &[Value]) -> Vec<Value> {
+        match self.layers.split_first() {
+            Some((first, rest)) => rest.iter().fold(first.call(x), |x, layer| layer.call(&x)),
+            None => x.to_vec(),
         }
-        x
     }
 }
 
 impl Module for MLP {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.call(x)
+    }
+
     fn parameters(&self) -> Vec<Value> {
-        self.layers.iter().flat_map(|l| l.parameters()).collect()
+        unique(self.layers.iter().flat_map(|l| l.parameters()))
+    }
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        unique_named(
+            self.layers
+                .iter()
+                .enumerate()
+                .flat_map(|(i, l)| prefixed(format!("layer{}", i), l.named_parameters())),
+        )
+    }
+}
+
+// Modules may share (tie) parameter nodes, so each node is reported once
+fn unique(params: impl Iterator<Item = Value>) -> Vec<Value> {
+    let mut seen = HashSet::new();
+    params.filter(|p| seen.insert(Rc::as_ptr(&p.0))).collect()
+}
+
+fn unique_named(named: impl Iterator<Item = (String, Value)>) -> Vec<(String, Value)> {
+    let mut seen = HashSet::new();
+    named
+        .filter(|(_, p)| seen.insert(Rc::as_ptr(&p.0)))
+        .collect()
+}
+
+fn prefixed(prefix: String, named: Vec<(String, Value)>) -> Vec<(String, Value)> {
+    named
+        .into_iter()
+        .map(|(name, p)| (format!("{}.{}", prefix, name), p))
+        .collect()
+}
+
+/// Randomly zeroes inputs with probability `p` while training, scaling the
+/// survivors by `1 / (1 - p)` so the expected activation is unchanged.
+pub struct Dropout {
+    p: f64,
+    rng: RefCell<StdRng>,
+    training: Cell<bool>,
+}
+
+impl Dropout {
+    pub fn new(p: f64) -> Self {
+        Self::new_seeded(p, rand::rng().random())
+    }
+
+    /// Masks are drawn from a `StdRng` seeded with `seed`, so two dropouts
+    /// built with the same seed drop the same units on every call.
+    pub fn new_seeded(p: f64, seed: u64) -> Self {
+        assert!((0.0..1.0).contains(&p));
+        Self {
+            p,
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+            training: Cell::new(true),
+        }
+    }
+}
+
+impl Module for Dropout {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        if !self.training.get() || self.p == 0.0 {
+            return x.to_vec();
+        }
+        let scale = 1.0 / (1.0 - self.p);
+        let mut rng = self.rng.borrow_mut();
+        x.iter()
+            .map(|xi| {
+                if rng.random::<f64>() < self.p {
+                    xi * 0.0
+                } else {
+                    xi * scale
+                }
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![]
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+    }
+}
+
+/// Adds `N(0, sigma^2)` noise to every input while training and is the
+/// identity at eval time. Noise enters as constant leaves, so gradients pass
+/// through unchanged.
+pub struct GaussianNoise {
+    pub sigma: f64,
+    rng: RefCell<StdRng>,
+    training: Cell<bool>,
+}
+
+impl GaussianNoise {
+    pub fn new(sigma: f64) -> Self {
+        Self::with_seed(sigma, rand::rng().random())
+    }
+
+    pub fn with_seed(sigma: f64, seed: u64) -> Self {
+        Self {
+            sigma,
+            rng: RefCell::new(StdRng::seed_from_u64(seed)),
+            training: Cell::new(true),
+        }
+    }
+}
+
+impl Module for GaussianNoise {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        if !self.training.get() {
+            return x.to_vec();
+        }
+        let mut rng = self.rng.borrow_mut();
+        x.iter()
+            .map(|xi| xi + self.sigma * standard_normal(&mut *rng))
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        vec![]
+    }
+
+    fn set_training(&self, training: bool) {
+        self.training.set(training);
+    }
+}
+
+/// Outputs the max over `k` affine pieces of the input.
+pub struct MaxoutNeuron {
+    pieces: Vec<Neuron>,
+}
+
+impl MaxoutNeuron {
+    pub fn new(nin: u64, k: usize) -> Self {
+        assert!(k > 0);
+        let pieces = (0..k)
+            .map(|_| Neuron::with_activation(nin, Activation::Linear))
+            .collect();
+        Self { pieces }
+    }
+
+    pub fn from_pieces(pieces: Vec<Neuron>) -> Self {
+        assert!(!pieces.is_empty());
+        Self { pieces }
+    }
+
+    pub fn pieces(&self) -> &[Neuron] {
+        &self.pieces
+    }
+
+    pub fn call(&self, x: &[Value]) -> Value {
+        let (first, rest) = self.pieces.split_first().unwrap();
+        rest.iter()
+            .fold(first.call(x), |best, piece| best.max(&piece.call(x)))
+    }
+}
+
+impl Module for MaxoutNeuron {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        vec![self.call(x)]
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.pieces.iter().flat_map(|p| p.parameters()).collect()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.pieces
+            .iter()
+            .enumerate()
+            .flat_map(|(i, p)| prefixed(format!("piece{}", i), p.named_parameters()))
+            .collect()
+    }
+}
+
+pub struct MaxoutLayer {
+    neurons: Vec<MaxoutNeuron>,
+}
+
+impl MaxoutLayer {
+    pub fn new(nin: u64, nout: u64, k: usize) -> Self {
+        let neurons = (0..nout).map(|_| MaxoutNeuron::new(nin, k)).collect();
+        Self { neurons }
+    }
+}
+
+impl Module for MaxoutLayer {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.neurons.iter().map(|n| n.call(x)).collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.neurons.iter().flat_map(|n| n.parameters()).collect()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.neurons
+            .iter()
+            .enumerate()
+            .flat_map(|(i, n)| prefixed(format!("neuron{}", i), n.named_parameters()))
+            .collect()
+    }
+}
+
+/// Parametric ReLU: `max(0, x) + a * min(0, x)` with a learnable slope `a`,
+/// either shared across all inputs or one per channel.
+pub struct PRelu {
+    slopes: Vec<Value>,
+}
+
+impl PRelu {
+    pub fn new(slope: f64) -> Self {
+        Self {
+            slopes: vec![Value::new(slope)],
+        }
+    }
+
+    pub fn per_channel(channels: usize, slope: f64) -> Self {
+        Self {
+            slopes: (0..channels).map(|_| Value::new(slope)).collect(),
+        }
+    }
+}
+
+impl Module for PRelu {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        x.iter()
+            .enumerate()
+            .map(|(i, xi)| {
+                let a = &self.slopes[i % self.slopes.len()];
+                // min(0, x) = -max(0, -x)
+                &xi.relu() - &(a * &(-xi).relu())
+            })
+            .collect()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.slopes.clone()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        self.slopes
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (format!("a{}", i), a.clone()))
+            .collect()
+    }
+}
+
+/// Elman recurrent cell: `h' = tanh(Wx x + Wh h + b)`. The same weights are
+/// reused at every timestep, so backward through an unrolled sequence sums
+/// each weight's gradient over time.
+pub struct RNNCell {
+    wx: Vec<Vec<Value>>,
+    wh: Vec<Vec<Value>>,
+    b: Vec<Value>,
+}
+
+impl RNNCell {
+    pub fn new(nin: u64, nhidden: u64) -> Self {
+        Self::new_seeded(nin, nhidden, &mut rand::rng())
+    }
+
+    /// Like [`RNNCell::new`], but draws the weights from `rng`.
+    pub fn new_seeded(nin: u64, nhidden: u64, rng: &mut impl Rng) -> Self {
+        let init = Init::default();
+        let fan_in = nin + nhidden;
+        let mut matrix = |cols: u64| -> Vec<Vec<Value>> {
+            (0..nhidden)
+                .map(|_| {
+                    (0..cols)
+                        .map(|_| Value::new(init.sample(rng, fan_in, nhidden)))
+                        .collect()
+                })
+                .collect()
+        };
+        let wx = matrix(nin);
+        let wh = matrix(nhidden);
+        let b = (0..nhidden).map(|_| Value::new(0.0)).collect();
+        Self { wx, wh, b }
+    }
+
+    pub fn hidden_size(&self) -> usize {
+        self.b.len()
+    }
+
+    /// All-zero hidden state to start a sequence from.
+    pub fn initial_state(&self) -> Vec<Value> {
+        (0..self.hidden_size()).map(|_| Value::new(0.0)).collect()
+    }
+
+    /// One timestep: the next hidden state from input `x` and state `h`.
+    pub fn step(&self, x: &[Value], h: &[Value]) -> Vec<Value> {
+        assert_eq!(
+            h.len(),
+            self.hidden_size(),
+            "RNNCell::step: got {} hidden values but the cell has {}",
+            h.len(),
+            self.hidden_size()
+        );
+        let xh: Vec<Value> = x.iter().chain(h).cloned().collect();
+        self.wx
+            .iter()
+            .zip(&self.wh)
+            .zip(&self.b)
+            .map(|((wx, wh), b)| {
+                let w: Vec<Value> = wx.iter().chain(wh).cloned().collect();
+                Value::linear(&xh, &w, b).tanh()
+            })
+            .collect()
+    }
+
+    /// Runs the cell over `sequence` from the zero state and returns the
+    /// hidden state after every step.
+    pub fn unroll(&self, sequence: &[Vec<Value>]) -> Vec<Vec<Value>> {
+        let mut h = self.initial_state();
+        sequence
+            .iter()
+            .map(|x| {
+                h = self.step(x, &h);
+                h.clone()
+            })
+            .collect()
+    }
+}
+
+/// A single step from the zero state.
+impl Module for RNNCell {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.step(x, &self.initial_state())
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.wx
+            .iter()
+            .chain(&self.wh)
+            .flatten()
+            .chain(&self.b)
+            .cloned()
+            .collect()
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        let matrix = |name: &str, m: &[Vec<Value>]| -> Vec<(String, Value)> {
+            m.iter()
+                .enumerate()
+                .flat_map(|(i, row)| {
+                    row.iter()
+                        .enumerate()
+                        .map(move |(j, w)| (format!("{}.{}.{}", name, i, j), w.clone()))
+                })
+                .collect()
+        };
+        let mut named = matrix("wx", &self.wx);
+        named.extend(matrix("wh", &self.wh));
+        named.extend(
+            self.b
+                .iter()
+                .enumerate()
+                .map(|(i, b)| (format!("b{}", i), b.clone())),
+        );
+        named
+    }
+}
+
+pub struct Sequential {
+    modules: Vec<Box<dyn Module>>,
+}
+
+impl Sequential {
+    pub fn new(modules: Vec<Box<dyn Module>>) -> Self {
+        Self { modules }
+    }
+}
+
+impl Module for Sequential {
+    fn forward(&self, x: &[Value]) -> Vec<Value> {
+        self.modules
+            .iter()
+            .fold(x.to_vec(), |x, module| module.forward(&x))
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        unique(self.modules.iter().flat_map(|m| m.parameters()))
+    }
+
+    fn named_parameters(&self) -> Vec<(String, Value)> {
+        unique_named(
+            self.modules
+                .iter()
+                .enumerate()
+                .flat_map(|(i, m)| prefixed(i.to_string(), m.named_parameters())),
+        )
+    }
+
+    fn set_training(&self, training: bool) {
+        for m in &self.modules {
+            m.set_training(training);
+        }
     }
 }
@@ -0,0 +1,362 @@
+use crate::engine::{Value, log_softmax};
+
+/// How per-element losses are combined.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reduction {
+    Mean,
+    Sum,
+    /// Keep one loss node per element, e.g. to weight them yourself.
+    None,
+}
+
+/// Result of a `*_with` loss: a single node for `Mean`/`Sum`, or the
+/// per-element nodes for `Reduction::None`.
+#[derive(Debug, Clone)]
+pub enum LossOutput {
+    Scalar(Value),
+    Elements(Vec<Value>),
+}
+
+impl LossOutput {
+    /// The reduced loss. Panics for `Reduction::None` outputs.
+    pub fn scalar(self) -> Value {
+        match self {
+            LossOutput::Scalar(v) => v,
+            LossOutput::Elements(_) => panic!("loss was computed with Reduction::None"),
+        }
+    }
+
+    /// The per-element losses; a reduced loss comes back as a single element.
+    pub fn elements(self) -> Vec<Value> {
+        match self {
+            LossOutput::Scalar(v) => vec![v],
+            LossOutput::Elements(v) => v,
+        }
+    }
+}
+
+fn reduce(terms: Vec<Value>, reduction: Reduction) -> LossOutput {
+    assert!(
+        reduction != Reduction::Mean || !terms.is_empty(),
+        "loss: cannot take the mean of zero samples"
+    );
+    let sum = || terms.iter().fold(Value::new(0.0), |acc, t| &acc + t);
+    match reduction {
+        Reduction::Mean => LossOutput::Scalar(&sum() * (1.0 / terms.len() as f64)),
+        Reduction::Sum => LossOutput::Scalar(sum()),
+        Reduction::None => LossOutput::Elements(terms),
+    }
+}
+
+/// Reduces per-sample losses (e.g. from a `*_with(.., Reduction::None)`
+/// loss) to a single node for `backward`, alongside each sample's loss as a
+/// plain number for logging or finding outliers.
+pub fn per_sample(terms: Vec<Value>, reduction: Reduction) -> (Value, Vec<f64>) {
+    assert!(
+        reduction != Reduction::None,
+        "per_sample: reduction must be Mean or Sum"
+    );
+    let values = terms.iter().map(|t| t.value()).collect();
+    (reduce(terms, reduction).scalar(), values)
+}
+
+/// `sum(w_i * l_i) / sum(w_i)`. Shared by the `*_weighted` losses.
+fn weighted_mean(name: &str, terms: Vec<Value>, weights: &[f64]) -> Value {
+    assert_eq!(
+        terms.len(),
+        weights.len(),
+        "{}: got {} samples but {} weights",
+        name,
+        terms.len(),
+        weights.len()
+    );
+    let total: f64 = weights.iter().sum();
+    assert!(total != 0.0, "{}: weights sum to zero", name);
+    let sum = terms
+        .iter()
+        .zip(weights)
+        .fold(Value::new(0.0), |acc, (t, &w)| &acc + &(t * w));
+    &sum * (1.0 / total)
+}
+
+fn check_len(name: &str, preds: usize, targets: usize) {
+    assert_eq!(
+        preds, targets,
+        "{}: got {} predictions but {} targets",
+        name, preds, targets
+    );
+}
+
+/// Mean squared error: `sum((p - t)^2) / N`.
+pub fn mse(preds: &[Value], targets: &[f64]) -> Value {
+    mse_with(preds, targets, Reduction::Mean).scalar()
+}
+
+pub fn mse_with(preds: &[Value], targets: &[f64], reduction: Reduction) -> LossOutput {
+    let targets: Vec<Value> = targets.iter().map(|&t| Value::new(t)).collect();
+    mse_values_with(preds, &targets, reduction)
+}
+
+/// Like [`mse`], but the targets are graph nodes and receive gradients too.
+pub fn mse_values(preds: &[Value], targets: &[Value]) -> Value {
+    mse_values_with(preds, targets, Reduction::Mean).scalar()
+}
+
+pub fn mse_values_with(preds: &[Value], targets: &[Value], reduction: Reduction) -> LossOutput {
+    check_len("mse", preds.len(), targets.len());
+    let terms = preds
+        .iter()
+        .zip(targets)
+        .map(|(p, t)| {
+            let diff = p - t;
+            &diff * &diff
+        })
+        .collect();
+    reduce(terms, reduction)
+}
+
+/// [`mse`] with a weight per sample; the mean divides by the sum of the
+/// weights rather than N. Negative weights are accepted and push the model
+/// away from those targets.
+pub fn mse_weighted(preds: &[Value], targets: &[f64], weights: &[f64]) -> Value {
+    let terms = mse_with(preds, targets, Reduction::None).elements();
+    weighted_mean("mse_weighted", terms, weights)
+}
+
+/// Mean absolute error: `sum(|p - t|) / N`. At zero error the gradient is
+/// 0, following [`Value::abs`].
+pub fn mae(preds: &[Value], targets: &[f64]) -> Value {
+    mae_with(preds, targets, Reduction::Mean).scalar()
+}
+
+pub fn mae_with(preds: &[Value], targets: &[f64], reduction: Reduction) -> LossOutput {
+    check_len("mae", preds.len(), targets.len());
+    let terms = preds
+        .iter()
+        .zip(targets)
+        .map(|(p, &t)| (p + -t).abs())
+        .collect();
+    reduce(terms, reduction)
+}
+
+/// Huber (smooth L1) loss, mean-reduced. Each element uses `e^2 / 2` when
+/// `|e| <= delta` and `delta * (|e| - delta / 2)` otherwise; the branch is
+/// picked when the graph is built, and both sides meet with matching slope.
+pub fn huber(preds: &[Value], targets: &[f64], delta: f64) -> Value {
+    huber_with(preds, targets, delta, Reduction::Mean).scalar()
+}
+
+pub fn huber_with(
+    preds: &[Value],
+    targets: &[f64],
+    delta: f64,
+    reduction: Reduction,
+) -> LossOutput {
+    check_len("huber", preds.len(), targets.len());
+    let terms = preds
+        .iter()
+        .zip(targets)
+        .map(|(p, &t)| {
+            let e = p + -t;
+            if e.value().abs() <= delta {
+                &(&e * &e) * 0.5
+            } else {
+                let abs_e = if e.value() > 0.0 { e } else { -&e };
+                &(&abs_e + -0.5 * delta) * delta
+            }
+        })
+        .collect();
+    reduce(terms, reduction)
+}
+
+/// Binary cross-entropy on raw logits, mean-reduced. Each element is
+/// `softplus(x) - t * x`, which equals `-(t ln p + (1 - t) ln(1 - p))` for
+/// `p = sigmoid(x)` without ever taking `ln(0)`. Targets may be soft.
+pub fn bce_with_logits(logits: &[Value], targets: &[f64]) -> Value {
+    bce_with_logits_with(logits, targets, Reduction::Mean).scalar()
+}
+
+pub fn bce_with_logits_with(logits: &[Value], targets: &[f64], reduction: Reduction) -> LossOutput {
+    check_len("bce_with_logits", logits.len(), targets.len());
+    let terms = logits
+        .iter()
+        .zip(targets)
+        .map(|(x, &t)| &x.softplus() - &(x * t))
+        .collect();
+    reduce(terms, reduction)
+}
+
+/// [`bce_with_logits`] with a weight per sample, normalised like
+/// [`mse_weighted`]. Negative weights are accepted.
+pub fn bce_with_logits_weighted(logits: &[Value], targets: &[f64], weights: &[f64]) -> Value {
+    let terms = bce_with_logits_with(logits, targets, Reduction::None).elements();
+    weighted_mean("bce_with_logits_weighted", terms, weights)
+}
+
+/// Binary focal loss on raw logits, mean-reduced:
+/// `-alpha * (1 - p_t)^gamma * ln(p_t)`, where `p_t` is the predicted
+/// probability of the target label (targets are 0 or 1). `gamma = 0`,
+/// `alpha = 1` is plain binary cross-entropy.
+///
+/// With `z = x` for positives and `-x` for negatives, `ln(p_t)` is built as
+/// `-softplus(-z)` and `1 - p_t` as `sigmoid(-z)`, so neither saturates to
+/// `ln(0)` for confident logits.
+pub fn focal(logits: &[Value], targets: &[f64], gamma: f64, alpha: f64) -> Value {
+    check_len("focal", logits.len(), targets.len());
+    assert!(
+        gamma >= 0.0,
+        "focal: gamma must be non-negative, got {}",
+        gamma
+    );
+    let terms = logits
+        .iter()
+        .zip(targets)
+        .map(|(x, &t)| {
+            assert!(
+                t == 0.0 || t == 1.0,
+                "focal: targets must be 0 or 1, got {}",
+                t
+            );
+            let z = if t == 1.0 { x.clone() } else { -x };
+            let log_pt = -&(-&z).softplus();
+            let ce = &log_pt * -alpha;
+            // pow(0) has a 0 * inf gradient once 1 - p_t underflows to 0
+            if gamma == 0.0 {
+                ce
+            } else {
+                &(-&z).sigmoid().pow(gamma) * &ce
+            }
+        })
+        .collect();
+    reduce(terms, Reduction::Mean).scalar()
+}
+
+/// Softmax cross-entropy for one sample: `-log_softmax(logits)[target]`.
+pub fn cross_entropy(logits: &[Value], target_class: usize) -> Value {
+    assert!(
+        target_class < logits.len(),
+        "cross_entropy: target class {} out of range for {} logits",
+        target_class,
+        logits.len()
+    );
+    -&log_softmax(logits)[target_class]
+}
+
+/// [`cross_entropy`] against a label-smoothed target: the true class gets
+/// probability `1 - eps + eps / K` and every other class `eps / K`, so each
+/// logit's gradient is `softmax_i - target_i`. `eps` must be in `[0, 1)`.
+pub fn cross_entropy_smoothed(logits: &[Value], target_class: usize, eps: f64) -> Value {
+    assert!(
+        (0.0..1.0).contains(&eps),
+        "cross_entropy_smoothed: label smoothing {} is not in [0, 1)",
+        eps
+    );
+    assert!(
+        target_class < logits.len(),
+        "cross_entropy_smoothed: target class {} out of range for {} logits",
+        target_class,
+        logits.len()
+    );
+    let k = logits.len() as f64;
+    log_softmax(logits)
+        .iter()
+        .enumerate()
+        .fold(Value::new(0.0), |acc, (i, lp)| {
+            let target = if i == target_class {
+                1.0 - eps + eps / k
+            } else {
+                eps / k
+            };
+            &acc + &(lp * -target)
+        })
+}
+
+/// Cross-entropy for one sample from probabilities rather than logits,
+/// e.g. a manually composed softmax: `-ln(clamp(probs[target], eps, 1 - eps))`.
+/// The clamp keeps the loss finite when the probability underflows to 0; as
+/// with [`Value::hardtanh`], a clamped probability gets zero gradient.
+/// `eps` must be in `(0, 0.5)`.
+pub fn cross_entropy_eps(probs: &[Value], target_class: usize, eps: f64) -> Value {
+    assert!(
+        eps > 0.0 && eps < 0.5,
+        "cross_entropy_eps: eps {} is not in (0, 0.5)",
+        eps
+    );
+    assert!(
+        target_class < probs.len(),
+        "cross_entropy_eps: target class {} out of range for {} probabilities",
+        target_class,
+        probs.len()
+    );
+    -&probs[target_class].hardtanh(eps, 1.0 - eps).log()
+}
+
+/// Mean of [`cross_entropy`] over a batch of samples.
+pub fn cross_entropy_batch(logits: &[Vec<Value>], targets: &[usize]) -> Value {
+    cross_entropy_batch_with(logits, targets, Reduction::Mean).scalar()
+}
+
+pub fn cross_entropy_batch_with(
+    logits: &[Vec<Value>],
+    targets: &[usize],
+    reduction: Reduction,
+) -> LossOutput {
+    assert_eq!(
+        logits.len(),
+        targets.len(),
+        "cross_entropy_batch: got {} samples but {} targets",
+        logits.len(),
+        targets.len()
+    );
+    let terms = logits
+        .iter()
+        .zip(targets)
+        .map(|(l, &t)| cross_entropy(l, t))
+        .collect();
+    reduce(terms, reduction)
+}
+
+/// [`cross_entropy_batch`] with a weight per sample, normalised like
+/// [`mse_weighted`]. Negative weights are accepted.
+pub fn cross_entropy_batch_weighted(
+    logits: &[Vec<Value>],
+    targets: &[usize],
+    weights: &[f64],
+) -> Value {
+    let terms = cross_entropy_batch_with(logits, targets, Reduction::None).elements();
+    weighted_mean("cross_entropy_batch_weighted", terms, weights)
+}
+
+/// Probabilities are floored at this value before taking their log in [`kl_div`].
+pub const KL_EPS: f64 = 1e-12;
+
+/// `KL(q || p) = sum(q_i * (ln(q_i) - p_log_i))`, where `p_log` holds
+/// log-probabilities (e.g. from `log_softmax`) and `q` holds probabilities.
+/// Zero entries of `q` contribute nothing; their log is taken of `KL_EPS`
+/// so no NaN appears in the forward or backward pass.
+///
+/// Unlike the other losses this defaults to `Reduction::Sum`, which is the
+/// divergence itself.
+pub fn kl_div(p_log: &[Value], q: &[Value]) -> Value {
+    kl_div_with(p_log, q, Reduction::Sum).scalar()
+}
+
+pub fn kl_div_with(p_log: &[Value], q: &[Value], reduction: Reduction) -> LossOutput {
+    assert_eq!(
+        p_log.len(),
+        q.len(),
+        "kl_div: got {} log-probabilities but {} probabilities",
+        p_log.len(),
+        q.len()
+    );
+    let floor = Value::new(KL_EPS);
+    let terms = p_log
+        .iter()
+        .zip(q)
+        .map(|(lp, qi)| {
+            let log_q = qi.max(&floor).log();
+            qi * &(&log_q - lp)
+        })
+        .collect();
+    reduce(terms, reduction)
+}
@@ -0,0 +1,40 @@
+use crate::engine::Value;
+
+/// `lambda * sum(w^2)` as a graph node, to be added to the loss. Its
+/// gradient on each parameter is `2 * lambda * w`.
+pub fn l2_penalty(params: &[Value], lambda: f64) -> Value {
+    let sum = params
+        .iter()
+        .fold(Value::new(0.0), |acc, w| &acc + &(w * w));
+    &sum * lambda
+}
+
+/// `lambda * sum(|w|)`. Its subgradient is `lambda * sign(w)` (0 at 0), a
+/// constant pull that drives weights the loss barely needs to zero.
+pub fn l1_penalty(params: &[Value], lambda: f64) -> Value {
+    let sum = params
+        .iter()
+        .fold(Value::new(0.0), |acc, w| &acc + &w.abs());
+    &sum * lambda
+}
+
+/// `l1 * sum(|w|) + l2 * sum(w^2)`.
+pub fn elastic_net(params: &[Value], l1: f64, l2: f64) -> Value {
+    &l1_penalty(params, l1) + &l2_penalty(params, l2)
+}
+
+/// [`l2_penalty`] over the parameters from `Module::named_parameters` whose
+/// name passes `keep`, e.g. `|name| !name.ends_with(".b")` to leave biases
+/// unregularized.
+pub fn l2_penalty_filtered(
+    named: &[(String, Value)],
+    lambda: f64,
+    keep: impl Fn(&str) -> bool,
+) -> Value {
+    let params: Vec<Value> = named
+        .iter()
+        .filter(|(name, _)| keep(name))
+        .map(|(_, p)| p.clone())
+        .collect();
+    l2_penalty(&params, lambda)
+}
@@ -0,0 +1,1150 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use rand::Rng;
+
+use crate::engine::{SGD, Value};
+use crate::init::standard_normal;
+
+pub trait Optimizer {
+    fn step(&mut self);
+    /// Zeroes the gradient of every parameter this optimizer updates, except
+    /// while an accumulation group is part-way through (see
+    /// [`set_accumulation_steps`](Optimizer::set_accumulation_steps)), when
+    /// it does nothing.
+    fn zero_grad(&self);
+    /// Clears any internal state (moment buffers, step counter) so the next
+    /// step behaves like the first one.
+    fn reset(&mut self);
+    fn step_count(&self) -> usize;
+    /// The parameters this optimizer updates, in the order its buffers use.
+    fn parameters(&self) -> Vec<Value>;
+    /// Learning rate. With several parameter groups this is the rate of the
+    /// first group that started with a nonzero one.
+    fn lr(&self) -> f64;
+    /// With several parameter groups, every group is rescaled so they keep
+    /// the ratios they were created with (or last given by `set_group_lr`)
+    /// and the first nonzero one gets `lr`.
+    fn set_lr(&mut self, lr: f64);
+    /// A copy of the internal buffers, e.g. for checkpointing.
+    fn state(&self) -> OptimizerState;
+    /// Restores everything captured by `state`. Nothing changes if its
+    /// buffers don't match this optimizer's names or parameter count.
+    fn load_state(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError>;
+    /// Only update on every `n`-th `step` call, using the gradients summed
+    /// over those calls divided by `n`, and zero them afterwards. While a
+    /// group is part-way accumulated `zero_grad` does nothing, so the usual
+    /// zero_grad/backward/step loop can stay as it is.
+    ///
+    /// The default implementation only accepts `n == 1`.
+    fn set_accumulation_steps(&mut self, n: usize) {
+        assert_eq!(
+            n, 1,
+            "set_accumulation_steps: this optimizer does not accumulate gradients"
+        );
+    }
+}
+
+/// Bookkeeping behind [`Optimizer::set_accumulation_steps`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Accumulation {
+    steps: usize,
+    pending: usize,
+}
+
+impl Default for Accumulation {
+    fn default() -> Self {
+        Self {
+            steps: 1,
+            pending: 0,
+        }
+    }
+}
+
+impl Accumulation {
+    pub(crate) fn set_steps(&mut self, n: usize) {
+        assert!(n > 0, "set_accumulation_steps: n must be positive");
+        self.steps = n;
+        self.pending = 0;
+    }
+
+    /// Counts one `step` call. Returns true once `steps` calls have been
+    /// seen, with the groups' grads averaged over them.
+    pub(crate) fn ready(&mut self, groups: &[ParamGroup]) -> bool {
+        self.pending += 1;
+        if self.pending < self.steps {
+            return false;
+        }
+        self.pending = 0;
+        if self.steps > 1 {
+            let scale = 1.0 / self.steps as f64;
+            for p in group_params(groups) {
+                p.0.borrow_mut().grad *= scale;
+            }
+        }
+        true
+    }
+
+    /// Called after an update; the averaged grads have been used up.
+    pub(crate) fn finish(&self, groups: &[ParamGroup]) {
+        if self.steps > 1 {
+            zero_grads(groups);
+        }
+    }
+
+    pub(crate) fn zero_grad(&self, groups: &[ParamGroup]) {
+        if self.pending == 0 {
+            zero_grads(groups);
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.pending = 0;
+    }
+
+    /// `step` calls counted towards the current group.
+    pub(crate) fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+/// Plain-data snapshot of an optimizer: its learning rate, step counter,
+/// how far into an accumulation group it is and each named per-parameter
+/// buffer (`velocity` for SGD, `m` and `v` for Adam), indexed like the
+/// optimizer's parameters.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OptimizerState {
+    pub lr: f64,
+    pub steps: usize,
+    /// `step` calls already accumulated towards the next update; see
+    /// [`Optimizer::set_accumulation_steps`].
+    pub pending: usize,
+    pub buffers: Vec<(String, Vec<f64>)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OptimizerStateError {
+    /// The state's buffer names differ from this optimizer's.
+    BufferMismatch {
+        expected: Vec<String>,
+        got: Vec<String>,
+    },
+    /// A buffer holds a different number of values than there are parameters.
+    LengthMismatch {
+        buffer: String,
+        expected: usize,
+        got: usize,
+    },
+}
+
+impl std::fmt::Display for OptimizerStateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OptimizerStateError::BufferMismatch { expected, got } => write!(
+                f,
+                "expected optimizer buffers {:?}, got {:?}",
+                expected, got
+            ),
+            OptimizerStateError::LengthMismatch {
+                buffer,
+                expected,
+                got,
+            } => write!(
+                f,
+                "buffer {} has {} values but the optimizer has {} parameters",
+                buffer, got, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OptimizerStateError {}
+
+impl OptimizerState {
+    /// Checks and copies the buffers and counters; the caller applies `lr`
+    /// once this succeeds.
+    fn restore(
+        &self,
+        steps: &mut usize,
+        accumulation: &mut Accumulation,
+        buffers: &mut [(&str, &mut Vec<f64>)],
+    ) -> Result<(), OptimizerStateError> {
+        let names: Vec<&str> = self.buffers.iter().map(|(n, _)| n.as_str()).collect();
+        let expected: Vec<&str> = buffers.iter().map(|(n, _)| *n).collect();
+        if names != expected {
+            return Err(OptimizerStateError::BufferMismatch {
+                expected: expected.iter().map(|n| n.to_string()).collect(),
+                got: names.iter().map(|n| n.to_string()).collect(),
+            });
+        }
+        for ((name, values), (_, target)) in self.buffers.iter().zip(buffers.iter()) {
+            if values.len() != target.len() {
+                return Err(OptimizerStateError::LengthMismatch {
+                    buffer: name.clone(),
+                    expected: target.len(),
+                    got: values.len(),
+                });
+            }
+        }
+        for ((_, values), (_, target)) in self.buffers.iter().zip(buffers.iter_mut()) {
+            target.copy_from_slice(values);
+        }
+        *steps = self.steps;
+        accumulation.pending = self.pending;
+        Ok(())
+    }
+}
+
+impl Optimizer for SGD {
+    fn step(&mut self) {
+        SGD::step(self);
+    }
+
+    fn zero_grad(&self) {
+        self.accumulation.zero_grad(&self.groups);
+    }
+
+    fn reset(&mut self) {
+        self.velocity.clear();
+        self.steps = 0;
+        self.accumulation.reset();
+    }
+
+    fn step_count(&self) -> usize {
+        self.steps
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        group_params(&self.groups).cloned().collect()
+    }
+
+    fn lr(&self) -> f64 {
+        self.lrs.lr(&self.groups)
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lrs.set_lr(&mut self.groups, lr);
+    }
+
+    fn state(&self) -> OptimizerState {
+        OptimizerState {
+            lr: self.lr(),
+            steps: self.steps,
+            pending: self.accumulation.pending(),
+            buffers: vec![("velocity".to_string(), self.velocity_or_zeros())],
+        }
+    }
+
+    fn load_state(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError> {
+        if self.velocity.is_empty() {
+            self.velocity = self.velocity_or_zeros();
+        }
+        state.restore(
+            &mut self.steps,
+            &mut self.accumulation,
+            &mut [("velocity", &mut self.velocity)],
+        )?;
+        self.set_lr(state.lr);
+        Ok(())
+    }
+
+    fn set_accumulation_steps(&mut self, n: usize) {
+        self.accumulation.set_steps(n);
+    }
+}
+
+fn zero_grads(groups: &[ParamGroup]) {
+    for p in group_params(groups) {
+        p.0.borrow_mut().grad = 0.0;
+    }
+}
+
+/// Every parameter of `groups`, in order.
+pub(crate) fn group_params(groups: &[ParamGroup]) -> impl Iterator<Item = &Value> {
+    groups.iter().flat_map(|g| &g.params)
+}
+
+/// Bookkeeping behind [`Optimizer::set_lr`] for optimizers with parameter
+/// groups: each group's base rate, and the group whose rate `lr` reports (the
+/// first that started nonzero). `set_lr` sets that group's rate and scales the
+/// others by the same factor relative to their bases.
+#[derive(Debug, Clone)]
+pub(crate) struct GroupLrs {
+    base: Vec<f64>,
+    reference: usize,
+}
+
+impl GroupLrs {
+    pub(crate) fn new(groups: &[ParamGroup]) -> Self {
+        let base: Vec<f64> = groups.iter().map(|g| g.lr).collect();
+        let reference = base.iter().position(|&lr| lr != 0.0).unwrap_or(0);
+        Self { base, reference }
+    }
+
+    pub(crate) fn lr(&self, groups: &[ParamGroup]) -> f64 {
+        groups.get(self.reference).map_or(0.0, |g| g.lr)
+    }
+
+    /// If every group started at 0 they all get `lr`.
+    pub(crate) fn set_lr(&self, groups: &mut [ParamGroup], lr: f64) {
+        let reference = self.base.get(self.reference).copied().unwrap_or(0.0);
+        for (i, (group, &base)) in groups.iter_mut().zip(&self.base).enumerate() {
+            group.lr = if i == self.reference || reference == 0.0 {
+                lr
+            } else {
+                base * (lr / reference)
+            };
+        }
+    }
+
+    /// Sets group `i`'s current rate and rebases it, so later `set_lr` calls
+    /// keep its new ratio to the reference group.
+    pub(crate) fn set_group_lr(&mut self, groups: &mut [ParamGroup], i: usize, lr: f64) {
+        assert!(
+            i < groups.len(),
+            "set_group_lr: group {} out of range for {} groups",
+            i,
+            groups.len()
+        );
+        // How far set_lr has moved the rates from their bases
+        let scale = groups[self.reference].lr / self.base[self.reference];
+        groups[i].lr = lr;
+        self.base[i] = if scale.is_finite() && scale != 0.0 {
+            lr / scale
+        } else {
+            lr
+        };
+    }
+}
+
+/// Clamps every parameter's gradient into `[-c, c]` in place. Call between
+/// `backward` and `step`.
+pub fn clip_grad_value(params: &[Value], c: f64) {
+    assert!(c >= 0.0, "clip_grad_value: c must be non-negative");
+    for p in params {
+        let mut data = p.0.borrow_mut();
+        data.grad = data.grad.clamp(-c, c);
+    }
+}
+
+/// Rescales all gradients together so their global L2 norm is at most
+/// `max_norm`, keeping their direction. Returns the norm before clipping, for
+/// logging. Call between `backward` and `step`.
+pub fn clip_grad_norm(params: &[Value], max_norm: f64) -> f64 {
+    assert!(
+        max_norm >= 0.0,
+        "clip_grad_norm: max_norm must be non-negative"
+    );
+    let norm = params
+        .iter()
+        .map(|p| p.0.borrow().grad.powi(2))
+        .sum::<f64>()
+        .sqrt();
+    if norm > max_norm {
+        let scale = max_norm / norm;
+        for p in params {
+            p.0.borrow_mut().grad *= scale;
+        }
+    }
+    norm
+}
+
+/// Gradient accumulation driven by losses: feed it one microbatch loss at a
+/// time and it steps `optimizer` once every `k` of them, through
+/// [`Optimizer::set_accumulation_steps`]. With mean-reduced microbatch losses
+/// of equal size the update uses exactly the full-batch mean gradient. Grads
+/// are zeroed after each update, never between microbatches.
+pub struct MicrobatchTrainer<O: Optimizer> {
+    pub optimizer: O,
+    pending: usize,
+}
+
+impl<O: Optimizer> MicrobatchTrainer<O> {
+    pub fn new(mut optimizer: O, k: usize) -> Self {
+        assert!(k > 0, "MicrobatchTrainer: k must be positive");
+        optimizer.set_accumulation_steps(k);
+        Self {
+            optimizer,
+            pending: 0,
+        }
+    }
+
+    /// Accumulates `loss`'s gradient; returns true if this completed a group
+    /// of `k` and the optimizer stepped.
+    pub fn backward(&mut self, loss: &Value) -> bool {
+        loss.backward();
+        let before = self.optimizer.step_count();
+        self.optimizer.step();
+        if self.optimizer.step_count() == before {
+            self.pending += 1;
+            return false;
+        }
+        // Accumulating optimizers already zeroed them, but not with k = 1
+        self.optimizer.zero_grad();
+        self.pending = 0;
+        true
+    }
+
+    /// Microbatches accumulated since the last step.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StepError {
+    /// Parameter `index` (in the optimizer's
+    /// [`parameters`](Optimizer::parameters) order) has a NaN or infinite
+    /// gradient.
+    NonFiniteGrad { index: usize, value: f64 },
+}
+
+impl std::fmt::Display for StepError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepError::NonFiniteGrad { index, value } => {
+                write!(f, "parameter {} has non-finite gradient {}", index, value)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StepError {}
+
+/// Wraps an optimizer so a NaN or infinite gradient can't poison the
+/// weights. Before each step every gradient of the optimizer's parameters is
+/// checked; the
+/// parameters with non-finite ones keep their data while the rest update as
+/// usual (the inner optimizer sees a zero gradient for them, so momentum
+/// buffers only decay). In strict mode any non-finite gradient instead
+/// fails the whole step and nothing is updated.
+pub struct FiniteGradGuard<O: Optimizer> {
+    pub optimizer: O,
+    strict: bool,
+    skipped: usize,
+}
+
+impl<O: Optimizer> FiniteGradGuard<O> {
+    pub fn new(optimizer: O) -> Self {
+        Self {
+            optimizer,
+            strict: false,
+            skipped: 0,
+        }
+    }
+
+    /// Make [`try_step`](FiniteGradGuard::try_step) return
+    /// [`StepError::NonFiniteGrad`] for the first bad gradient instead of
+    /// skipping it. [`Optimizer::step`] has no way to report the error, so
+    /// it keeps skipping bad gradients either way.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Steps the inner optimizer; returns how many parameters were skipped.
+    pub fn try_step(&mut self) -> Result<usize, StepError> {
+        let params = self.optimizer.parameters();
+        let bad: Vec<(usize, f64)> = params
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, p.0.borrow().grad))
+            .filter(|(_, g)| !g.is_finite())
+            .collect();
+        if let (true, Some(&(index, value))) = (self.strict, bad.first()) {
+            return Err(StepError::NonFiniteGrad { index, value });
+        }
+
+        let saved: Vec<f64> = bad
+            .iter()
+            .map(|&(i, _)| {
+                let mut data = params[i].0.borrow_mut();
+                data.grad = 0.0;
+                data.data
+            })
+            .collect();
+        self.optimizer.step();
+        for (&(i, grad), data) in bad.iter().zip(saved) {
+            let mut p = params[i].0.borrow_mut();
+            p.data = data;
+            p.grad = grad;
+        }
+        self.skipped += bad.len();
+        Ok(bad.len())
+    }
+
+    /// Parameter updates skipped over the guard's lifetime.
+    pub fn skipped(&self) -> usize {
+        self.skipped
+    }
+}
+
+/// `step` skips non-finite gradients even in strict mode; use
+/// [`try_step`](FiniteGradGuard::try_step) to see the error.
+impl<O: Optimizer> Optimizer for FiniteGradGuard<O> {
+    fn step(&mut self) {
+        let strict = std::mem::replace(&mut self.strict, false);
+        let _ = self.try_step();
+        self.strict = strict;
+    }
+
+    fn zero_grad(&self) {
+        self.optimizer.zero_grad();
+    }
+
+    fn reset(&mut self) {
+        self.optimizer.reset();
+    }
+
+    fn step_count(&self) -> usize {
+        self.optimizer.step_count()
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        self.optimizer.parameters()
+    }
+
+    fn lr(&self) -> f64 {
+        self.optimizer.lr()
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.optimizer.set_lr(lr);
+    }
+
+    fn state(&self) -> OptimizerState {
+        self.optimizer.state()
+    }
+
+    fn load_state(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError> {
+        self.optimizer.load_state(state)
+    }
+
+    fn set_accumulation_steps(&mut self, n: usize) {
+        self.optimizer.set_accumulation_steps(n);
+    }
+}
+
+/// Swaps a set of weights into some parameters and back again. Shared by
+/// [`Ema`] and [`Swa`].
+#[derive(Default)]
+struct WeightSwap {
+    backup: RefCell<Option<Vec<f64>>>,
+}
+
+impl WeightSwap {
+    /// Writes `weights` into `params`, keeping the live values unless an
+    /// earlier swap is still in place.
+    fn apply(&self, params: &[Value], weights: &[f64]) {
+        let mut backup = self.backup.borrow_mut();
+        if backup.is_none() {
+            *backup = Some(params.iter().map(|p| p.value()).collect());
+        }
+        for (p, &w) in params.iter().zip(weights) {
+            p.0.borrow_mut().data = w;
+        }
+    }
+
+    fn restore(&self, params: &[Value]) {
+        if let Some(backup) = self.backup.borrow_mut().take() {
+            for (p, d) in params.iter().zip(backup) {
+                p.0.borrow_mut().data = d;
+            }
+        }
+    }
+
+    fn is_applied(&self) -> bool {
+        self.backup.borrow().is_some()
+    }
+}
+
+/// Exponential moving average of parameter values, for evaluating with
+/// smoothed weights. Call [`update`](Ema::update) after each optimizer step;
+/// [`apply`](Ema::apply) swaps the averages into the parameters and
+/// [`restore`](Ema::restore) puts the training weights back.
+pub struct Ema {
+    params: Vec<Value>,
+    decay: f64,
+    shadow: Vec<f64>,
+    swap: WeightSwap,
+}
+
+impl Ema {
+    /// The average starts from the parameters' current values.
+    pub fn new(params: Vec<Value>, decay: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&decay),
+            "Ema: decay {} is not in [0, 1]",
+            decay
+        );
+        let shadow = params.iter().map(|p| p.value()).collect();
+        Self {
+            params,
+            decay,
+            shadow,
+            swap: WeightSwap::default(),
+        }
+    }
+
+    /// `shadow = decay * shadow + (1 - decay) * data`.
+    pub fn update(&mut self) {
+        assert!(
+            !self.swap.is_applied(),
+            "Ema::update: called while the averaged weights are applied"
+        );
+        update_shadow(&mut self.shadow, &self.params, self.decay);
+    }
+
+    pub fn shadow(&self) -> &[f64] {
+        &self.shadow
+    }
+
+    /// Writes the averaged weights into the parameters, keeping the live
+    /// ones for [`restore`](Ema::restore). Applying twice keeps the first
+    /// backup.
+    pub fn apply(&self) {
+        self.swap.apply(&self.params, &self.shadow);
+    }
+
+    /// Undoes [`apply`](Ema::apply); does nothing if it wasn't applied.
+    pub fn restore(&self) {
+        self.swap.restore(&self.params);
+    }
+}
+
+/// Stochastic Weight Averaging: an equal-weight running average of the
+/// parameters, taken once per epoch from `start_epoch` on. Pair it with a
+/// constant or cyclical learning rate for the averaging phase (see
+/// [`SwaLR`](crate::scheduler::SwaLR)) and [`apply`](Swa::apply) the average
+/// at the end of training.
+pub struct Swa {
+    params: Vec<Value>,
+    pub start_epoch: usize,
+    average: Vec<f64>,
+    count: usize,
+    swap: WeightSwap,
+}
+
+impl Swa {
+    pub fn new(params: Vec<Value>, start_epoch: usize) -> Self {
+        let n = params.len();
+        Self {
+            params,
+            start_epoch,
+            average: vec![0.0; n],
+            count: 0,
+            swap: WeightSwap::default(),
+        }
+    }
+
+    /// Call at the end of `epoch`. From `start_epoch` on, folds the current
+    /// weights into the average (`avg = (avg * n + data) / (n + 1)`) and
+    /// returns true.
+    pub fn update(&mut self, epoch: usize) -> bool {
+        if epoch < self.start_epoch {
+            return false;
+        }
+        assert!(
+            !self.swap.is_applied(),
+            "Swa::update: called while the averaged weights are applied"
+        );
+        // Same average, but snapshots equal to it leave it bit-for-bit unchanged
+        let n = self.count as f64;
+        for (a, p) in self.average.iter_mut().zip(&self.params) {
+            *a += (p.value() - *a) / (n + 1.0);
+        }
+        self.count += 1;
+        true
+    }
+
+    pub fn average(&self) -> &[f64] {
+        &self.average
+    }
+
+    /// Snapshots averaged so far.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Writes the averaged weights into the parameters, keeping the live
+    /// ones for [`restore`](Swa::restore).
+    pub fn apply(&self) {
+        assert!(self.count > 0, "Swa::apply: no snapshots averaged yet");
+        self.swap.apply(&self.params, &self.average);
+    }
+
+    /// Undoes [`apply`](Swa::apply); does nothing if it wasn't applied.
+    pub fn restore(&self) {
+        self.swap.restore(&self.params);
+    }
+}
+
+/// Adds `N(0, std^2)` noise to every parameter's gradient in place. Call
+/// between `backward` and `step`; annealing `std` over epochs (e.g. with an
+/// [`LrScheduler`](crate::scheduler::LrScheduler)'s `lr(epoch)`) gives the
+/// usual decaying gradient noise.
+pub fn add_grad_noise(params: &[Value], std: f64, rng: &mut impl Rng) {
+    assert!(std >= 0.0, "add_grad_noise: std must be non-negative");
+    for p in params {
+        p.0.borrow_mut().grad += std * standard_normal(rng);
+    }
+}
+
+/// A set of parameters sharing hyperparameters, for [`SGD::from_groups`] and
+/// [`Adam::from_groups`], e.g. a small `lr` for early layers or
+/// `weight_decay: 0.0` for biases.
+#[derive(Clone)]
+pub struct ParamGroup {
+    pub params: Vec<Value>,
+    pub lr: f64,
+    pub weight_decay: f64,
+}
+
+impl ParamGroup {
+    pub fn new(params: Vec<Value>, lr: f64) -> Self {
+        Self {
+            params,
+            lr,
+            weight_decay: 0.0,
+        }
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+}
+
+/// Exponential moving average of parameter values (Polyak averaging). Keep
+/// one alongside the optimizer, `update` it after every step, and `copy_to`
+/// the model before evaluating. Unlike [`Ema`] it doesn't hold the
+/// parameters, the decay or a backup of the training weights.
+#[allow(clippy::upper_case_acronyms)]
+pub struct EMA {
+    pub shadow: Vec<f64>,
+}
+
+impl EMA {
+    /// Starts the averages at the parameters' current values.
+    pub fn new(params: &[Value]) -> Self {
+        Self {
+            shadow: params.iter().map(|p| p.value()).collect(),
+        }
+    }
+
+    /// `shadow = decay * shadow + (1 - decay) * data` for each parameter.
+    pub fn update(&mut self, params: &[Value], decay: f64) {
+        assert_eq!(
+            params.len(),
+            self.shadow.len(),
+            "EMA: got {} params but tracking {}",
+            params.len(),
+            self.shadow.len()
+        );
+        update_shadow(&mut self.shadow, params, decay);
+    }
+
+    /// Overwrites the parameters' data with the averaged values.
+    pub fn copy_to(&self, params: &[Value]) {
+        assert_eq!(
+            params.len(),
+            self.shadow.len(),
+            "EMA: got {} params but tracking {}",
+            params.len(),
+            self.shadow.len()
+        );
+        for (s, p) in self.shadow.iter().zip(params) {
+            p.0.borrow_mut().data = *s;
+        }
+    }
+}
+
+/// `shadow = decay * shadow + (1 - decay) * data`, shared by [`Ema`] and
+/// [`EMA`].
+fn update_shadow(shadow: &mut [f64], params: &[Value], decay: f64) {
+    for (s, p) in shadow.iter_mut().zip(params) {
+        *s = decay * *s + (1.0 - decay) * p.value();
+    }
+}
+
+pub struct Adam {
+    /// Private so its layout always matches `lrs`, `m` and `v`.
+    groups: Vec<ParamGroup>,
+    pub beta1: f64,
+    pub beta2: f64,
+    pub eps: f64,
+    lrs: GroupLrs,
+    m: Vec<f64>,
+    v: Vec<f64>,
+    t: usize,
+    accumulation: Accumulation,
+}
+
+impl Adam {
+    pub fn new(params: Vec<Value>, lr: f64) -> Self {
+        Self::from_groups(vec![ParamGroup::new(params, lr)])
+    }
+
+    /// One optimizer over several [`ParamGroup`]s, each with its own learning
+    /// rate and weight decay.
+    pub fn from_groups(groups: Vec<ParamGroup>) -> Self {
+        let n = group_params(&groups).count();
+        Self {
+            lrs: GroupLrs::new(&groups),
+            groups,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            m: vec![0.0; n],
+            v: vec![0.0; n],
+            t: 0,
+            accumulation: Accumulation::default(),
+        }
+    }
+
+    pub fn builder() -> AdamBuilder {
+        AdamBuilder::default()
+    }
+
+    pub fn groups(&self) -> &[ParamGroup] {
+        &self.groups
+    }
+
+    /// Sets group `i`'s learning rate; later [`set_lr`](Optimizer::set_lr)
+    /// calls scale it from there.
+    pub fn set_group_lr(&mut self, i: usize, lr: f64) {
+        self.lrs.set_group_lr(&mut self.groups, i, lr);
+    }
+}
+
+impl Optimizer for Adam {
+    fn step(&mut self) {
+        if !self.accumulation.ready(&self.groups) {
+            return;
+        }
+        self.t += 1;
+        let bias1 = 1.0 - self.beta1.powi(self.t as i32);
+        let bias2 = 1.0 - self.beta2.powi(self.t as i32);
+        let slots = self
+            .groups
+            .iter()
+            .flat_map(|g| g.params.iter().map(move |p| (g, p)));
+        for (((group, p), m), v) in slots.zip(&mut self.m).zip(&mut self.v) {
+            let mut data = p.0.borrow_mut();
+            if !data.requires_grad {
+                continue;
+            }
+            let g = data.grad + group.weight_decay * data.data;
+            *m = self.beta1 * *m + (1.0 - self.beta1) * g;
+            *v = self.beta2 * *v + (1.0 - self.beta2) * g * g;
+            let m_hat = *m / bias1;
+            let v_hat = *v / bias2;
+            data.data -= group.lr * m_hat / (v_hat.sqrt() + self.eps);
+        }
+        self.accumulation.finish(&self.groups);
+    }
+
+    fn zero_grad(&self) {
+        self.accumulation.zero_grad(&self.groups);
+    }
+
+    fn reset(&mut self) {
+        self.m.iter_mut().for_each(|m| *m = 0.0);
+        self.v.iter_mut().for_each(|v| *v = 0.0);
+        self.t = 0;
+        self.accumulation.reset();
+    }
+
+    fn step_count(&self) -> usize {
+        self.t
+    }
+
+    fn parameters(&self) -> Vec<Value> {
+        group_params(&self.groups).cloned().collect()
+    }
+
+    fn lr(&self) -> f64 {
+        self.lrs.lr(&self.groups)
+    }
+
+    fn set_lr(&mut self, lr: f64) {
+        self.lrs.set_lr(&mut self.groups, lr);
+    }
+
+    fn state(&self) -> OptimizerState {
+        OptimizerState {
+            lr: self.lr(),
+            steps: self.t,
+            pending: self.accumulation.pending(),
+            buffers: vec![
+                ("m".to_string(), self.m.clone()),
+                ("v".to_string(), self.v.clone()),
+            ],
+        }
+    }
+
+    fn load_state(&mut self, state: &OptimizerState) -> Result<(), OptimizerStateError> {
+        state.restore(
+            &mut self.t,
+            &mut self.accumulation,
+            &mut [("m", &mut self.m), ("v", &mut self.v)],
+        )?;
+        self.set_lr(state.lr);
+        Ok(())
+    }
+
+    fn set_accumulation_steps(&mut self, n: usize) {
+        self.accumulation.set_steps(n);
+    }
+}
+
+/// Limited-memory BFGS for small, full-batch, deterministic problems.
+/// Keeps the last `history_size` (step, gradient change) pairs to build a
+/// quasi-Newton direction, then searches along it starting from a step of
+/// `lr`, backtracking until the loss decreases enough (Armijo condition) and
+/// lengthening the step while the slope is still steep. With `history_size`
+/// 0 it is gradient descent with a line search.
+///
+/// Unlike the other optimizers, [`step`](LBFGS::step) takes a closure that
+/// rebuilds the graph, zeroes the grads, calls `backward` and returns the
+/// loss; it is called once per step plus once per line-search trial.
+#[allow(clippy::upper_case_acronyms)]
+pub struct LBFGS {
+    pub params: Vec<Value>,
+    pub history_size: usize,
+    pub lr: f64,
+    history: VecDeque<(Vec<f64>, Vec<f64>)>,
+}
+
+impl LBFGS {
+    /// Trial steps before the line search gives up.
+    const MAX_LINE_SEARCH: usize = 40;
+
+    pub fn new(params: Vec<Value>, history_size: usize, lr: f64) -> Self {
+        Self {
+            params,
+            history_size,
+            lr,
+            history: VecDeque::new(),
+        }
+    }
+
+    /// One iteration; returns the loss at the new parameters. If no step
+    /// along the search direction lowers the loss, the parameters are left
+    /// where they were and the starting loss is returned.
+    pub fn step(&mut self, mut closure: impl FnMut() -> f64) -> f64 {
+        let loss = closure();
+        let x: Vec<f64> = self.params.iter().map(|p| p.value()).collect();
+        let g = self.grads();
+
+        let mut d = self.direction(&g);
+        let mut slope = dot(&g, &d);
+        if slope >= 0.0 {
+            // The curvature pairs no longer describe a descent direction
+            self.history.clear();
+            d = g.iter().map(|v| -v).collect();
+            slope = -dot(&g, &g);
+        }
+        if slope == 0.0 {
+            return loss;
+        }
+
+        // Bisection on the weak Wolfe conditions: halve the step while the
+        // loss does not drop enough, double it while the slope is still
+        // steep. The second condition guarantees s.y > 0 for the new pair.
+        let (mut lo, mut hi, mut t) = (0.0, f64::INFINITY, self.lr);
+        for _ in 0..Self::MAX_LINE_SEARCH {
+            for ((p, xi), di) in self.params.iter().zip(&x).zip(&d) {
+                p.0.borrow_mut().data = xi + t * di;
+            }
+            let new_loss = closure();
+            let new_g = self.grads();
+            // Written so a NaN loss also counts as too far
+            let sufficient = new_loss <= loss + 1e-4 * t * slope;
+            if !sufficient {
+                hi = t;
+            } else if dot(&new_g, &d) < 0.9 * slope {
+                lo = t;
+            } else {
+                let s: Vec<f64> = d.iter().map(|di| t * di).collect();
+                let y: Vec<f64> = new_g.iter().zip(&g).map(|(a, b)| a - b).collect();
+                if self.history_size > 0 && dot(&s, &y) > 0.0 {
+                    if self.history.len() == self.history_size {
+                        self.history.pop_front();
+                    }
+                    self.history.push_back((s, y));
+                }
+                return new_loss;
+            }
+            t = if hi.is_finite() {
+                (lo + hi) / 2.0
+            } else {
+                2.0 * t
+            };
+        }
+        // No step met both conditions; settle for the last sufficient decrease
+        for ((p, xi), di) in self.params.iter().zip(&x).zip(&d) {
+            p.0.borrow_mut().data = xi + lo * di;
+        }
+        if lo > 0.0 { closure() } else { loss }
+    }
+
+    fn grads(&self) -> Vec<f64> {
+        self.params.iter().map(|p| p.0.borrow().grad).collect()
+    }
+
+    /// `-H g` by the two-loop recursion, with `H0 = (s.y / y.y) I` from the
+    /// newest pair.
+    fn direction(&self, g: &[f64]) -> Vec<f64> {
+        let mut q = g.to_vec();
+        let mut alphas = Vec::with_capacity(self.history.len());
+        for (s, y) in self.history.iter().rev() {
+            let alpha = dot(s, &q) / dot(y, s);
+            q.iter_mut().zip(y).for_each(|(qi, yi)| *qi -= alpha * yi);
+            alphas.push(alpha);
+        }
+        let gamma = self
+            .history
+            .back()
+            .map_or(1.0, |(s, y)| dot(s, y) / dot(y, y));
+        let mut r: Vec<f64> = q.iter().map(|qi| gamma * qi).collect();
+        for ((s, y), alpha) in self.history.iter().zip(alphas.iter().rev()) {
+            let beta = dot(y, &r) / dot(y, s);
+            r.iter_mut()
+                .zip(s)
+                .for_each(|(ri, si)| *ri += (alpha - beta) * si);
+        }
+        r.iter().map(|ri| -ri).collect()
+    }
+}
+
+fn dot(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+pub struct SgdBuilder {
+    lr: f64,
+    momentum: f64,
+    nesterov: bool,
+    weight_decay: f64,
+    no_decay: Vec<Value>,
+}
+
+impl Default for SgdBuilder {
+    fn default() -> Self {
+        Self {
+            lr: 0.01,
+            momentum: 0.0,
+            nesterov: false,
+            weight_decay: 0.0,
+            no_decay: vec![],
+        }
+    }
+}
+
+impl SgdBuilder {
+    pub fn lr(mut self, lr: f64) -> Self {
+        self.lr = lr;
+        self
+    }
+
+    pub fn momentum(mut self, momentum: f64) -> Self {
+        self.momentum = momentum;
+        self
+    }
+
+    pub fn nesterov(mut self, nesterov: bool) -> Self {
+        self.nesterov = nesterov;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    /// Exempts `params` (typically biases) from `weight_decay`.
+    pub fn no_decay(mut self, params: &[Value]) -> Self {
+        self.no_decay.extend_from_slice(params);
+        self
+    }
+
+    /// `no_decay` parameters go into groups of their own, so `params` is
+    /// split into runs that keep its order.
+    pub fn build(self, params: Vec<Value>) -> SGD {
+        let mut groups: Vec<ParamGroup> = vec![];
+        let mut last_decays = None;
+        for p in params {
+            let decays = !self.no_decay.iter().any(|q| q.same_node(&p));
+            match groups.last_mut() {
+                Some(group) if last_decays == Some(decays) => group.params.push(p),
+                _ => {
+                    let wd = if decays { self.weight_decay } else { 0.0 };
+                    groups.push(ParamGroup::new(vec![p], self.lr).weight_decay(wd));
+                }
+            }
+            last_decays = Some(decays);
+        }
+        if groups.is_empty() {
+            groups.push(ParamGroup::new(vec![], self.lr).weight_decay(self.weight_decay));
+        }
+        let mut sgd = SGD::from_groups(groups);
+        sgd.momentum = self.momentum;
+        sgd.nesterov = self.nesterov;
+        sgd
+    }
+}
+
+pub struct AdamBuilder {
+    lr: f64,
+    beta1: f64,
+    beta2: f64,
+    eps: f64,
+    weight_decay: f64,
+}
+
+impl Default for AdamBuilder {
+    fn default() -> Self {
+        Self {
+            lr: 0.001,
+            beta1: 0.9,
+            beta2: 0.999,
+            eps: 1e-8,
+            weight_decay: 0.0,
+        }
+    }
+}
+
+impl AdamBuilder {
+    pub fn lr(mut self, lr: f64) -> Self {
+        self.lr = lr;
+        self
+    }
+
+    pub fn betas(mut self, beta1: f64, beta2: f64) -> Self {
+        self.beta1 = beta1;
+        self.beta2 = beta2;
+        self
+    }
+
+    pub fn eps(mut self, eps: f64) -> Self {
+        self.eps = eps;
+        self
+    }
+
+    pub fn weight_decay(mut self, weight_decay: f64) -> Self {
+        self.weight_decay = weight_decay;
+        self
+    }
+
+    pub fn build(self, params: Vec<Value>) -> Adam {
+        let mut adam = Adam::from_groups(vec![
+            ParamGroup::new(params, self.lr).weight_decay(self.weight_decay),
+        ]);
+        adam.beta1 = self.beta1;
+        adam.beta2 = self.beta2;
+        adam.eps = self.eps;
+        adam
+    }
+}
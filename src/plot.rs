@@ -0,0 +1,282 @@
+use std::fs::File;
+use std::io::{self, BufWriter};
+
+const WIDTH: usize = 640;
+const HEIGHT: usize = 400;
+const LEFT: usize = 80;
+const RIGHT: usize = 20;
+const TOP: usize = 30;
+const BOTTOM: usize = 50;
+/// Each font pixel is drawn as a `SCALE`x`SCALE` block.
+pub(crate) const SCALE: usize = 2;
+
+type Rgb = [u8; 3];
+const WHITE: Rgb = [255, 255, 255];
+const BLACK: Rgb = [0, 0, 0];
+const GRID: Rgb = [225, 225, 225];
+const LINE: Rgb = [31, 119, 180];
+const BAD: Rgb = [214, 39, 40];
+
+/// Renders `history` (one loss per epoch) as a line chart and writes it to
+/// `path` as a PNG. Both axes are scaled to the data; non-finite entries are
+/// skipped and break the line.
+pub fn plot_loss(history: &[f64], path: &str) -> io::Result<()> {
+    let mut canvas = Canvas::new();
+    let (x0, x1) = (LEFT, WIDTH - RIGHT);
+    let (y0, y1) = (TOP, HEIGHT - BOTTOM);
+
+    let (lo, hi) = value_range(history.iter().copied());
+    let last = history.len().saturating_sub(1).max(1) as f64;
+    let px = |i: f64| x0 as f64 + i / last * (x1 - x0) as f64;
+    let py = |v: f64| y1 as f64 - (v - lo) / (hi - lo) * (y1 - y0) as f64;
+
+    const TICKS: usize = 5;
+    let mut prev_epoch = None;
+    for t in 0..TICKS {
+        let frac = t as f64 / (TICKS - 1) as f64;
+        let y = py(lo + frac * (hi - lo)).round() as usize;
+        canvas.hline(x0, x1, y, GRID);
+        let label = tick_label(lo + frac * (hi - lo));
+        let text_x = x0.saturating_sub(6 + text_width(&label));
+        canvas.text(text_x, y.saturating_sub(5 * SCALE / 2), &label, BLACK);
+
+        // Short histories round several ticks onto the same epoch
+        let epoch = (frac * last).round();
+        if prev_epoch.replace(epoch) == Some(epoch) {
+            continue;
+        }
+        let x = px(epoch).round() as usize;
+        canvas.vline(x, y0, y1, GRID);
+        let label = format!("{}", epoch as usize);
+        canvas.text(
+            x.saturating_sub(text_width(&label) / 2),
+            y1 + 6,
+            &label,
+            BLACK,
+        );
+    }
+    canvas.hline(x0, x1, y1, BLACK);
+    canvas.vline(x0, y0, y1, BLACK);
+    canvas.text(
+        (x0 + x1 - text_width("epoch")) / 2,
+        HEIGHT - 18,
+        "epoch",
+        BLACK,
+    );
+    canvas.text(8, 8, "loss", BLACK);
+
+    let mut prev: Option<(f64, f64)> = None;
+    for (i, &v) in history.iter().enumerate() {
+        if !v.is_finite() {
+            prev = None;
+            continue;
+        }
+        let point = (px(i as f64), py(v));
+        canvas.line(prev.unwrap_or(point), point, LINE);
+        prev = Some(point);
+    }
+
+    canvas.write_png(path)
+}
+
+/// Renders `grads` as a bar chart of their magnitudes, one bar per
+/// parameter in order, and writes it to `path` as a PNG. The y axis starts
+/// at zero; non-finite gradients are drawn as full-height red bars so they
+/// stand out instead of wrecking the scale.
+pub(crate) fn plot_grads(grads: &[f64], path: &str) -> io::Result<()> {
+    let mut canvas = Canvas::new();
+    let (x0, x1) = (LEFT, WIDTH - RIGHT);
+    let (y0, y1) = (TOP, HEIGHT - BOTTOM);
+
+    let (_, hi) = value_range(grads.iter().map(|g| g.abs()).chain([0.0]));
+    let py = |v: f64| y1 as f64 - v / hi * (y1 - y0) as f64;
+    let bar_width = (x1 - x0) as f64 / grads.len().max(1) as f64;
+
+    const TICKS: usize = 5;
+    for t in 0..TICKS {
+        let v = t as f64 / (TICKS - 1) as f64 * hi;
+        let y = py(v).round() as usize;
+        canvas.hline(x0, x1, y, GRID);
+        let label = tick_label(v);
+        let text_x = x0.saturating_sub(6 + text_width(&label));
+        canvas.text(text_x, y.saturating_sub(5 * SCALE / 2), &label, BLACK);
+    }
+
+    // Leave a one-pixel gap between bars once there is room for it
+    let gap = usize::from(bar_width >= 3.0);
+    for (i, &g) in grads.iter().enumerate() {
+        let (top, color) = if g.is_finite() {
+            (py(g.abs()).round() as usize, LINE)
+        } else {
+            (y0, BAD)
+        };
+        let left = x0 + (i as f64 * bar_width).round() as usize;
+        let next = x0 + ((i + 1) as f64 * bar_width).round() as usize;
+        let right = next.saturating_sub(1 + gap);
+        for x in left..=right.max(left) {
+            canvas.vline(x, top, y1, color);
+        }
+    }
+
+    let mut prev_index = None;
+    for t in 0..TICKS {
+        let frac = t as f64 / (TICKS - 1) as f64;
+        let index = (frac * grads.len().saturating_sub(1) as f64).round() as usize;
+        if prev_index.replace(index) == Some(index) {
+            continue;
+        }
+        let label = format!("{}", index);
+        let x = x0 + ((index as f64 + 0.5) * bar_width).round() as usize;
+        canvas.text(
+            x.saturating_sub(text_width(&label) / 2),
+            y1 + 6,
+            &label,
+            BLACK,
+        );
+    }
+    canvas.hline(x0, x1, y1, BLACK);
+    canvas.vline(x0, y0, y1, BLACK);
+    canvas.text(
+        (x0 + x1 - text_width("parameter")) / 2,
+        HEIGHT - 18,
+        "parameter",
+        BLACK,
+    );
+    canvas.text(8, 8, "|grad|", BLACK);
+
+    canvas.write_png(path)
+}
+
+/// Smallest and largest finite value, padded apart if they coincide.
+fn value_range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let finite = values.filter(|v| v.is_finite());
+    let (lo, hi) = finite.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+        (lo.min(v), hi.max(v))
+    });
+    if lo > hi {
+        (0.0, 1.0)
+    } else if lo == hi {
+        let pad = if lo == 0.0 { 1.0 } else { lo.abs() * 0.1 };
+        (lo - pad, hi + pad)
+    } else {
+        (lo, hi)
+    }
+}
+
+fn tick_label(v: f64) -> String {
+    if v != 0.0 && (v.abs() >= 1e4 || v.abs() < 1e-3) {
+        format!("{:.1e}", v)
+    } else {
+        format!("{:.3}", v)
+    }
+}
+
+fn text_width(s: &str) -> usize {
+    s.chars().count() * 4 * SCALE
+}
+
+/// 3x5 glyphs, one row per entry with the leftmost pixel in bit 2. Letters
+/// are drawn upper-case whatever case they are passed in.
+pub(crate) fn glyph(c: char) -> [u8; 5] {
+    match c.to_ascii_uppercase() {
+        '0' => [7, 5, 5, 5, 7],
+        'O' => [2, 5, 5, 5, 2],
+        '1' => [2, 6, 2, 2, 7],
+        '2' => [7, 1, 7, 4, 7],
+        '3' => [7, 1, 7, 1, 7],
+        '4' => [5, 5, 7, 1, 1],
+        '5' => [7, 4, 6, 1, 6],
+        'S' => [3, 4, 2, 1, 6],
+        '6' => [7, 4, 7, 5, 7],
+        '7' => [7, 1, 1, 1, 1],
+        '8' => [7, 5, 7, 5, 7],
+        '9' => [7, 5, 7, 1, 7],
+        '.' => [0, 0, 0, 0, 2],
+        '-' => [0, 0, 7, 0, 0],
+        'E' => [7, 4, 7, 4, 7],
+        'P' => [7, 5, 7, 4, 4],
+        'C' => [7, 4, 4, 4, 7],
+        'H' => [5, 5, 7, 5, 5],
+        'L' => [4, 4, 4, 4, 7],
+        'A' => [2, 5, 7, 5, 5],
+        'R' => [6, 5, 6, 5, 5],
+        'M' => [5, 7, 7, 5, 5],
+        'T' => [7, 2, 2, 2, 2],
+        'G' => [7, 4, 5, 5, 7],
+        'D' => [6, 5, 5, 5, 6],
+        '|' => [2, 2, 2, 2, 2],
+        _ => [0; 5],
+    }
+}
+
+struct Canvas {
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new() -> Self {
+        Self {
+            pixels: WHITE.repeat(WIDTH * HEIGHT),
+        }
+    }
+
+    fn set(&mut self, x: usize, y: usize, color: Rgb) {
+        if x < WIDTH && y < HEIGHT {
+            let i = 3 * (y * WIDTH + x);
+            self.pixels[i..i + 3].copy_from_slice(&color);
+        }
+    }
+
+    fn hline(&mut self, x0: usize, x1: usize, y: usize, color: Rgb) {
+        (x0..=x1).for_each(|x| self.set(x, y, color));
+    }
+
+    fn vline(&mut self, x: usize, y0: usize, y1: usize, color: Rgb) {
+        (y0..=y1).for_each(|y| self.set(x, y, color));
+    }
+
+    /// Two pixels thick so the curve stands out from the grid.
+    fn line(&mut self, from: (f64, f64), to: (f64, f64), color: Rgb) {
+        let steps = (to.0 - from.0).abs().max((to.1 - from.1).abs()).ceil() as usize;
+        for s in 0..=steps {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                s as f64 / steps as f64
+            };
+            let x = (from.0 + t * (to.0 - from.0)).round() as usize;
+            let y = (from.1 + t * (to.1 - from.1)).round() as usize;
+            for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                self.set(x + dx, y + dy, color);
+            }
+        }
+    }
+
+    fn text(&mut self, x: usize, y: usize, s: &str, color: Rgb) {
+        for (i, c) in s.chars().enumerate() {
+            let left = x + i * 4 * SCALE;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..3 {
+                    if bits & (4 >> col) != 0 {
+                        for dy in 0..SCALE {
+                            for dx in 0..SCALE {
+                                self.set(left + col * SCALE + dx, y + row * SCALE + dy, color);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn write_png(&self, path: &str) -> io::Result<()> {
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+}
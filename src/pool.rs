@@ -0,0 +1,121 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::engine::{Data, Value};
+
+type Node = Rc<RefCell<Data>>;
+
+#[derive(Default)]
+struct PoolState {
+    live: Vec<Node>,
+    free: Vec<Node>,
+    allocations: usize,
+    reuses: usize,
+}
+
+thread_local! {
+    static ACTIVE: RefCell<Option<PoolState>> = const { RefCell::new(None) };
+}
+
+/// Recycles graph nodes between forward passes. Every node built inside
+/// [`scope`](ValuePool::scope) comes from the pool; when the scope ends, nodes
+/// that nothing outside the pool still references are unlinked and handed out
+/// again by the next scope instead of being freed and reallocated.
+///
+/// Nodes that are still referenced (e.g. a loss returned from the scope) are
+/// left untouched until a later scope finds them unreferenced, so recycling
+/// never changes a value or gradient the caller can observe.
+#[derive(Default)]
+pub struct ValuePool {
+    state: PoolState,
+}
+
+impl ValuePool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs `f` with this pool serving every node created on this thread.
+    /// Nodes that became unreachable are recycled before and after `f`, so a
+    /// graph returned from one scope is reused once the caller drops it.
+    pub fn scope<R>(&mut self, f: impl FnOnce() -> R) -> R {
+        self.recycle();
+        // Restores the previous pool (if scopes nest) even if `f` panics.
+        struct Guard<'a> {
+            pool: &'a mut PoolState,
+            outer: Option<PoolState>,
+        }
+        impl Drop for Guard<'_> {
+            fn drop(&mut self) {
+                let outer = self.outer.take();
+                *self.pool = ACTIVE
+                    .with(|a| std::mem::replace(&mut *a.borrow_mut(), outer))
+                    .unwrap_or_default();
+            }
+        }
+
+        let state = std::mem::take(&mut self.state);
+        let guard = Guard {
+            outer: ACTIVE.with(|a| a.borrow_mut().replace(state)),
+            pool: &mut self.state,
+        };
+        let out = f();
+        drop(guard);
+        self.recycle();
+        out
+    }
+
+    /// Nodes allocated fresh (as opposed to recycled) over the pool's lifetime.
+    pub fn allocations(&self) -> usize {
+        self.state.allocations
+    }
+
+    /// Nodes handed out again from the free list.
+    pub fn reuses(&self) -> usize {
+        self.state.reuses
+    }
+
+    fn recycle(&mut self) {
+        let state = &mut self.state;
+        // `live` is in creation order and a node is always created after its
+        // parents, so walking newest-first releases each consumer's hold on
+        // its parents before those parents are checked.
+        let mut kept = vec![];
+        while let Some(node) = state.live.pop() {
+            if Rc::strong_count(&node) == 1 {
+                let mut data = node.borrow_mut();
+                data.parents.clear();
+                data._backward = None;
+                drop(data);
+                state.free.push(node);
+            } else {
+                kept.push(node);
+            }
+        }
+        kept.reverse();
+        state.live = kept;
+    }
+}
+
+/// Wraps `data` in a node, reusing a pooled allocation inside a
+/// [`ValuePool::scope`].
+pub(crate) fn alloc(data: Data) -> Value {
+    ACTIVE.with(|a| match a.borrow_mut().as_mut() {
+        Some(state) => {
+            let node = match state.free.pop() {
+                Some(node) => {
+                    state.reuses += 1;
+                    *node.borrow_mut() = data;
+                    node
+                }
+                None => {
+                    state.allocations += 1;
+                    Rc::new(RefCell::new(data))
+                }
+            };
+            state.live.push(node.clone());
+            Value(node)
+        }
+        None => Value(Rc::new(RefCell::new(data))),
+    })
+}
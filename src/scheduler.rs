@@ -0,0 +1,270 @@
+use crate::optim::Optimizer;
+
+/// A learning-rate schedule indexed by epoch. Call `step` once at the end of
+/// every epoch; it advances the schedule and writes the next epoch's rate
+/// into the optimizer via `set_lr`.
+pub trait LrScheduler {
+    /// Learning rate for `epoch` (0-based).
+    fn lr(&self, epoch: usize) -> f64;
+    fn step(&mut self, optimizer: &mut dyn Optimizer);
+}
+
+/// Multiplies the learning rate by `gamma` every `step_size` epochs.
+pub struct StepLR {
+    pub initial_lr: f64,
+    pub step_size: usize,
+    pub gamma: f64,
+    epoch: usize,
+}
+
+impl StepLR {
+    pub fn new(initial_lr: f64, step_size: usize, gamma: f64) -> Self {
+        assert!(step_size > 0, "StepLR: step_size must be positive");
+        Self {
+            initial_lr,
+            step_size,
+            gamma,
+            epoch: 0,
+        }
+    }
+}
+
+impl LrScheduler for StepLR {
+    fn lr(&self, epoch: usize) -> f64 {
+        self.initial_lr * self.gamma.powi((epoch / self.step_size) as i32)
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.epoch += 1;
+        optimizer.set_lr(self.lr(self.epoch));
+    }
+}
+
+/// Half-cosine decay from `lr_max` to `lr_min` over `t_max` epochs, then held
+/// at `lr_min`. With [`with_restarts`](Self::with_restarts) the schedule
+/// instead jumps back to `lr_max` at the end of each cycle, each cycle being
+/// `t_mult` times longer than the last (SGDR).
+pub struct CosineAnnealingLR {
+    pub lr_max: f64,
+    pub lr_min: f64,
+    pub t_max: usize,
+    pub t_mult: Option<usize>,
+    epoch: usize,
+}
+
+impl CosineAnnealingLR {
+    pub fn new(lr_max: f64, lr_min: f64, t_max: usize) -> Self {
+        assert!(t_max > 0, "CosineAnnealingLR: t_max must be positive");
+        Self {
+            lr_max,
+            lr_min,
+            t_max,
+            t_mult: None,
+            epoch: 0,
+        }
+    }
+
+    pub fn with_restarts(mut self, t_mult: usize) -> Self {
+        assert!(t_mult > 0, "CosineAnnealingLR: t_mult must be positive");
+        self.t_mult = Some(t_mult);
+        self
+    }
+}
+
+impl LrScheduler for CosineAnnealingLR {
+    fn lr(&self, epoch: usize) -> f64 {
+        let (t, period) = match self.t_mult {
+            None => (epoch.min(self.t_max), self.t_max),
+            Some(t_mult) => {
+                let (mut t, mut period) = (epoch, self.t_max);
+                while t >= period {
+                    t -= period;
+                    period *= t_mult;
+                }
+                (t, period)
+            }
+        };
+        let progress = t as f64 / period as f64;
+        self.lr_min
+            + 0.5 * (self.lr_max - self.lr_min) * (1.0 + (std::f64::consts::PI * progress).cos())
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.epoch += 1;
+        optimizer.set_lr(self.lr(self.epoch));
+    }
+}
+
+/// Ramps the learning rate linearly from `floor` (0 by default) up to
+/// `inner`'s starting rate over the first `warmup_steps`, then runs `inner`
+/// from its own step 0.
+pub struct Warmup {
+    pub inner: Box<dyn LrScheduler>,
+    pub warmup_steps: usize,
+    pub floor: f64,
+    epoch: usize,
+}
+
+impl Warmup {
+    pub fn new(inner: Box<dyn LrScheduler>, warmup_steps: usize) -> Self {
+        Self {
+            inner,
+            warmup_steps,
+            floor: 0.0,
+            epoch: 0,
+        }
+    }
+
+    pub fn with_floor(mut self, floor: f64) -> Self {
+        self.floor = floor;
+        self
+    }
+}
+
+impl LrScheduler for Warmup {
+    fn lr(&self, epoch: usize) -> f64 {
+        if epoch >= self.warmup_steps {
+            return self.inner.lr(epoch - self.warmup_steps);
+        }
+        let progress = epoch as f64 / self.warmup_steps as f64;
+        self.floor + (self.inner.lr(0) - self.floor) * progress
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.epoch += 1;
+        optimizer.set_lr(self.lr(self.epoch));
+    }
+}
+
+/// Triangular cyclical schedule: climbs linearly from `base_lr` to `max_lr`
+/// over `step_size_up` steps, falls back over `step_size_down`, and repeats.
+pub struct CyclicalLR {
+    pub base_lr: f64,
+    pub max_lr: f64,
+    pub step_size_up: usize,
+    pub step_size_down: usize,
+    epoch: usize,
+}
+
+impl CyclicalLR {
+    pub fn new(base_lr: f64, max_lr: f64, step_size_up: usize, step_size_down: usize) -> Self {
+        assert!(
+            step_size_up > 0 && step_size_down > 0,
+            "CyclicalLR: step sizes must be positive"
+        );
+        Self {
+            base_lr,
+            max_lr,
+            step_size_up,
+            step_size_down,
+            epoch: 0,
+        }
+    }
+
+    /// Steps in one full cycle.
+    pub fn period(&self) -> usize {
+        self.step_size_up + self.step_size_down
+    }
+}
+
+impl LrScheduler for CyclicalLR {
+    fn lr(&self, epoch: usize) -> f64 {
+        let pos = epoch % self.period();
+        let amplitude = self.max_lr - self.base_lr;
+        if pos < self.step_size_up {
+            self.base_lr + amplitude * pos as f64 / self.step_size_up as f64
+        } else {
+            let down = (pos - self.step_size_up) as f64 / self.step_size_down as f64;
+            self.max_lr - amplitude * down
+        }
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.epoch += 1;
+        optimizer.set_lr(self.lr(self.epoch));
+    }
+}
+
+/// SGDR: cosine cycles from `lr_max` down to `lr_min`, restarting at
+/// `lr_max` after each one. The first cycle lasts `t_0` steps and each
+/// following cycle `t_mult` times longer. Same schedule as
+/// `CosineAnnealingLR::new(lr_max, lr_min, t_0).with_restarts(t_mult)`.
+pub struct CosineAnnealingWarmRestarts {
+    schedule: CosineAnnealingLR,
+}
+
+impl CosineAnnealingWarmRestarts {
+    pub fn new(lr_max: f64, lr_min: f64, t_0: usize, t_mult: usize) -> Self {
+        Self {
+            schedule: CosineAnnealingLR::new(lr_max, lr_min, t_0).with_restarts(t_mult),
+        }
+    }
+
+    /// Steps at which a new cycle begins (after step 0), up to `limit`.
+    pub fn restarts(&self, limit: usize) -> Vec<usize> {
+        let t_mult = self.schedule.t_mult.unwrap_or(1);
+        let (mut at, mut period) = (self.schedule.t_max, self.schedule.t_max);
+        let mut restarts = vec![];
+        while at <= limit {
+            restarts.push(at);
+            period *= t_mult;
+            at += period;
+        }
+        restarts
+    }
+}
+
+impl LrScheduler for CosineAnnealingWarmRestarts {
+    fn lr(&self, epoch: usize) -> f64 {
+        self.schedule.lr(epoch)
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.schedule.step(optimizer);
+    }
+}
+
+/// Learning rate for SWA training: follows `before` until `swa_start`, then
+/// holds `swa_lr` for the averaging phase. [`with_cycle`](SwaLR::with_cycle)
+/// runs a cyclical schedule there instead, from its own step 0.
+pub struct SwaLR {
+    pub before: Box<dyn LrScheduler>,
+    pub swa_start: usize,
+    pub swa_lr: f64,
+    pub cycle: Option<Box<dyn LrScheduler>>,
+    epoch: usize,
+}
+
+impl SwaLR {
+    pub fn new(before: Box<dyn LrScheduler>, swa_start: usize, swa_lr: f64) -> Self {
+        Self {
+            before,
+            swa_start,
+            swa_lr,
+            cycle: None,
+            epoch: 0,
+        }
+    }
+
+    pub fn with_cycle(mut self, cycle: Box<dyn LrScheduler>) -> Self {
+        self.cycle = Some(cycle);
+        self
+    }
+}
+
+impl LrScheduler for SwaLR {
+    fn lr(&self, epoch: usize) -> f64 {
+        if epoch < self.swa_start {
+            return self.before.lr(epoch);
+        }
+        match &self.cycle {
+            Some(cycle) => cycle.lr(epoch - self.swa_start),
+            None => self.swa_lr,
+        }
+    }
+
+    fn step(&mut self, optimizer: &mut dyn Optimizer) {
+        self.epoch += 1;
+        optimizer.set_lr(self.lr(self.epoch));
+    }
+}
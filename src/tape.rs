@@ -0,0 +1,175 @@
+use crate::engine::stable_sigmoid;
+
+/// Handle to a node on a [`Tape`]; only meaningful for the tape that made it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Var(usize);
+
+#[derive(Debug, Clone, Copy)]
+enum TapeOp {
+    Leaf,
+    Add(usize, usize),
+    Mul(usize, usize),
+    Tanh(usize),
+    Exp(usize),
+    Log(usize),
+    Pow(usize, f64),
+    Relu(usize),
+    Sigmoid(usize),
+}
+
+#[derive(Debug)]
+struct Node {
+    data: f64,
+    grad: f64,
+    op: TapeOp,
+}
+
+/// Arena-backed alternative to [`Value`](crate::engine::Value) for large
+/// graphs: nodes live contiguously in one `Vec` and refer to their parents
+/// by index. A node is always pushed after its parents, so the arena is
+/// already in topological order and `backward` is a single reverse sweep
+/// with no hashing, recursion or closures.
+///
+/// ```
+/// use autodiff_rs::tape::Tape;
+///
+/// let mut tape = Tape::new();
+/// let x = tape.leaf(2.0);
+/// let y = tape.leaf(3.0);
+/// let xy = tape.mul(x, y);
+/// let out = tape.tanh(xy);
+/// tape.backward(out);
+/// assert!(tape.grad(x) > 0.0);
+/// ```
+#[derive(Debug, Default)]
+pub struct Tape {
+    nodes: Vec<Node>,
+}
+
+impl Tape {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            nodes: Vec::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    pub fn value(&self, v: Var) -> f64 {
+        self.nodes[v.0].data
+    }
+
+    pub fn grad(&self, v: Var) -> f64 {
+        self.nodes[v.0].grad
+    }
+
+    fn push(&mut self, data: f64, op: TapeOp) -> Var {
+        self.nodes.push(Node {
+            data,
+            grad: 0.0,
+            op,
+        });
+        Var(self.nodes.len() - 1)
+    }
+
+    pub fn leaf(&mut self, data: f64) -> Var {
+        self.push(data, TapeOp::Leaf)
+    }
+
+    pub fn add(&mut self, a: Var, b: Var) -> Var {
+        self.push(self.value(a) + self.value(b), TapeOp::Add(a.0, b.0))
+    }
+
+    pub fn mul(&mut self, a: Var, b: Var) -> Var {
+        self.push(self.value(a) * self.value(b), TapeOp::Mul(a.0, b.0))
+    }
+
+    pub fn tanh(&mut self, a: Var) -> Var {
+        self.push(self.value(a).tanh(), TapeOp::Tanh(a.0))
+    }
+
+    pub fn exp(&mut self, a: Var) -> Var {
+        self.push(self.value(a).exp(), TapeOp::Exp(a.0))
+    }
+
+    pub fn log(&mut self, a: Var) -> Var {
+        self.push(self.value(a).ln(), TapeOp::Log(a.0))
+    }
+
+    pub fn pow(&mut self, a: Var, exponent: f64) -> Var {
+        self.push(self.value(a).powf(exponent), TapeOp::Pow(a.0, exponent))
+    }
+
+    pub fn relu(&mut self, a: Var) -> Var {
+        self.push(self.value(a).max(0.0), TapeOp::Relu(a.0))
+    }
+
+    pub fn sigmoid(&mut self, a: Var) -> Var {
+        self.push(stable_sigmoid(self.value(a)), TapeOp::Sigmoid(a.0))
+    }
+
+    /// Pairwise sum, so the result is `log2(n)` deep rather than `n`.
+    pub fn sum(&mut self, xs: &[Var]) -> Var {
+        match xs.len() {
+            0 => self.leaf(0.0),
+            1 => xs[0],
+            n => {
+                let (left, right) = xs.split_at(n / 2);
+                let l = self.sum(left);
+                let r = self.sum(right);
+                self.add(l, r)
+            }
+        }
+    }
+
+    pub fn zero_grad(&mut self) {
+        self.nodes.iter_mut().for_each(|n| n.grad = 0.0);
+    }
+
+    /// Resets every grad, then backpropagates from `root`. Unlike
+    /// `Value::backward`, gradients don't accumulate across calls.
+    pub fn backward(&mut self, root: Var) {
+        self.zero_grad();
+        self.nodes[root.0].grad = 1.0;
+        for i in (0..=root.0).rev() {
+            let Node { data, grad, op } = self.nodes[i];
+            if grad == 0.0 {
+                continue;
+            }
+            match op {
+                TapeOp::Leaf => {}
+                TapeOp::Add(a, b) => {
+                    self.nodes[a].grad += grad;
+                    self.nodes[b].grad += grad;
+                }
+                TapeOp::Mul(a, b) => {
+                    let (da, db) = (self.nodes[a].data, self.nodes[b].data);
+                    self.nodes[a].grad += db * grad;
+                    self.nodes[b].grad += da * grad;
+                }
+                TapeOp::Tanh(a) => self.nodes[a].grad += (1.0 - data * data) * grad,
+                TapeOp::Exp(a) => self.nodes[a].grad += data * grad,
+                TapeOp::Log(a) => self.nodes[a].grad += grad / self.nodes[a].data,
+                TapeOp::Pow(a, e) => {
+                    self.nodes[a].grad += e * self.nodes[a].data.powf(e - 1.0) * grad;
+                }
+                TapeOp::Relu(a) => {
+                    if data > 0.0 {
+                        self.nodes[a].grad += grad;
+                    }
+                }
+                TapeOp::Sigmoid(a) => self.nodes[a].grad += data * (1.0 - data) * grad,
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::engine::{Value, no_grad};
+use crate::nn::Module;
+use crate::optim::Optimizer;
+
+/// Per-epoch losses recorded by [`Trainer::fit`]. `val_loss` is empty when
+/// no validation set was given.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    pub train_loss: Vec<f64>,
+    pub val_loss: Vec<f64>,
+}
+
+/// Full-batch training loop for single-output models. `loss_fn` maps the
+/// model's predictions and the targets to a scalar loss, e.g. `losses::mse`.
+pub struct Trainer<'a, L: Fn(&[Value], &[f64]) -> Value> {
+    pub model: &'a dyn Module,
+    pub optimizer: &'a mut dyn Optimizer,
+    loss_fn: L,
+}
+
+impl<'a, L: Fn(&[Value], &[f64]) -> Value> Trainer<'a, L> {
+    pub fn new(model: &'a dyn Module, optimizer: &'a mut dyn Optimizer, loss_fn: L) -> Self {
+        Self {
+            model,
+            optimizer,
+            loss_fn,
+        }
+    }
+
+    /// Runs `epochs` optimizer steps on the whole of `inputs`/`targets`.
+    /// After each one, `validation` (if any) is scored in eval mode without
+    /// building a graph.
+    pub fn fit(
+        &mut self,
+        inputs: &[Vec<f64>],
+        targets: &[f64],
+        epochs: usize,
+        validation: Option<(&[Vec<f64>], &[f64])>,
+    ) -> History {
+        let mut history = History::default();
+        for _ in 0..epochs {
+            self.model.set_training(true);
+            self.optimizer.zero_grad();
+            let loss = (self.loss_fn)(&self.predict(inputs), targets);
+            loss.backward();
+            self.optimizer.step();
+            history.train_loss.push(loss.value());
+
+            if let Some((val_inputs, val_targets)) = validation {
+                history
+                    .val_loss
+                    .push(self.evaluate(val_inputs, val_targets));
+            }
+        }
+        history
+    }
+
+    /// Like [`fit`](Self::fit), holding out the last `val_ratio` of the
+    /// samples for validation.
+    pub fn fit_split(
+        &mut self,
+        inputs: &[Vec<f64>],
+        targets: &[f64],
+        epochs: usize,
+        val_ratio: f64,
+    ) -> History {
+        assert!(
+            (0.0..1.0).contains(&val_ratio),
+            "fit_split: val_ratio must be in [0, 1), got {}",
+            val_ratio
+        );
+        let split = inputs.len() - (inputs.len() as f64 * val_ratio).round() as usize;
+        let (train_x, val_x) = inputs.split_at(split);
+        let (train_y, val_y) = targets.split_at(split);
+        let validation = (!val_x.is_empty()).then_some((val_x, val_y));
+        self.fit(train_x, train_y, epochs, validation)
+    }
+
+    /// Loss on `inputs`/`targets` in eval mode, without recording a graph.
+    /// Leaves the model in eval mode.
+    pub fn evaluate(&self, inputs: &[Vec<f64>], targets: &[f64]) -> f64 {
+        self.model.set_training(false);
+        no_grad(|| (self.loss_fn)(&self.predict(inputs), targets).value())
+    }
+
+    fn predict(&self, inputs: &[Vec<f64>]) -> Vec<Value> {
+        assert!(!inputs.is_empty(), "Trainer: no samples");
+        inputs
+            .iter()
+            .map(|x| {
+                let x: Vec<Value> = x.iter().map(|&v| Value::new(v)).collect();
+                self.model.forward(&x)[0].clone()
+            })
+            .collect()
+    }
+}
@@ -1,9 +1,8 @@
 use crate::engine::Value;
 
-
-
 pub struct GraphVisualizer {
     pub root: Value,
+    pub max_depth: Option<usize>,
     pub centered: bool,
 }
 impl eframe::App for GraphVisualizer {
@@ -22,7 +21,7 @@ impl eframe::App for GraphVisualizer {
                     let origin = rect.center();
 
                     // Draw graph
-                    let root_rect = self.root.render_node(ui, origin);
+                    let root_rect = self.root.render_node_to_depth(ui, origin, self.max_depth);
 
                     // 👇 Scroll ONCE to center the graph
                     if !self.centered {